@@ -1,6 +1,8 @@
 use criterion::{criterion_group, criterion_main, Criterion, BenchmarkId};
 use rosu_nps::{calc_nps, calc_nps_range_by_time, calc_nps_range_by_hitobjects, calc_distribution, calc_distribution_2, calc_distribution_smart};
 use rosu_map::Beatmap;
+#[cfg(feature = "rayon")]
+use rosu_nps::calc_distribution_parallel;
 
 fn load_test_map() -> Beatmap {
     let b = include_bytes!("../assets/8thera.osu");
@@ -119,6 +121,15 @@ fn bench_distribution_comparison(c: &mut Criterion) {
                 b.iter(|| calc_distribution_smart(&map, parts))
             },
         );
+
+        #[cfg(feature = "rayon")]
+        group.bench_with_input(
+            BenchmarkId::new("calc_distribution_parallel", parts),
+            &parts,
+            |b, &parts| {
+                b.iter(|| calc_distribution_parallel(&map, parts))
+            },
+        );
     }
     
     group.finish();
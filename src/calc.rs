@@ -1,16 +1,209 @@
-use rosu_map::Beatmap;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead};
+#[cfg(feature = "rayon")]
+use std::path::PathBuf;
+#[cfg(feature = "zip")]
+use std::io::{Cursor, Read};
+
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+use rosu_map::{Beatmap, DecodeBeatmap, DecodeState, LATEST_FORMAT_VERSION};
+use rosu_map::section::Section;
+use rosu_map::section::general::GameMode;
+use rosu_map::section::hit_objects::{
+    CurveBuffers, HitObject, HitObjectKind, HitObjectSlider, HitObjects, HitObjectsState, ParseHitObjectsError,
+};
+use rosu_map::section::hit_objects::hit_samples::HitSampleInfo;
+use rosu_map::section::timing_points::TimingPoint;
+use rosu_map::util::Pos;
+use serde::Serialize;
 
 // Constantes pour améliorer la lisibilité
 const MS_TO_SEC: f64 = 1000.0;
+const MS_PER_MINUTE: f64 = 60_000.0;
+const PLAYFIELD_WIDTH: f32 = 512.0;
+const PLAYFIELD_HEIGHT: f32 = 384.0;
+const SNAP_DIVISORS: [u32; 6] = [1, 2, 3, 4, 6, 8];
+const SNAP_TOLERANCE_MS: f64 = 2.0;
+const DIFFICULTY_WINDOW_MS: f64 = 1000.0;
+const DIFFICULTY_STREAM_MIN_NPS: f64 = 8.0;
+const SLIDER_TICK_MIN_END_MS: f64 = 10.0;
+const BANANA_MAX_SPACING_MS: f64 = 100.0;
+// Taille max d'un .osu lu depuis une archive, la taille annoncée par l'en-tête zip n'est pas fiable
+#[cfg(feature = "zip")]
+const OSZ_ENTRY_MAX_BYTES: u64 = 16 * 1024 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CalcError {
+    EmptyMap,
+    InvalidDuration,
+    IntervalTooSmall,
+    ParseFailed,
+    TimingOutOfRange,
+    UnsortedTimings,
+}
+
+impl fmt::Display for CalcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let msg = match self {
+            CalcError::EmptyMap => "beatmap has no hit objects",
+            CalcError::InvalidDuration => "duration must be positive",
+            CalcError::IntervalTooSmall => "interval must be at least 1ms",
+            CalcError::ParseFailed => "failed to parse beatmap",
+            CalcError::TimingOutOfRange => "timings must be finite and between 0 and i32::MAX ms",
+            CalcError::UnsortedTimings => "timings must be sorted ascending",
+        };
+        f.write_str(msg)
+    }
+}
+
+impl Error for CalcError {}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParseError {
+    MissingHitObjects,
+    EmptyHitObjects,
+    ReadFailed(io::ErrorKind),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::MissingHitObjects => f.write_str("missing [HitObjects] section"),
+            ParseError::EmptyHitObjects => f.write_str("[HitObjects] section is empty"),
+            ParseError::ReadFailed(kind) => write!(f, "failed to read beatmap: {kind}"),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct KeyValue {
+    pub key: i32,
+    pub value: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct DistributionStats {
+    pub mean: f64,
+    pub median: f64,
+    pub std_dev: f64,
+    pub max: f64,
+    pub min: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MapDensitySummary {
+    pub overall_nps: f64,
+    pub peak_nps: f64,
+    pub peak_time_ms: f64,
+    pub stream_count: usize,
+    pub total_objects: usize,
+    pub drain_time_ms: f64,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct MapAnalysis {
+    pub overall_nps: f64,
+    pub peak_nps: f64,
+    pub distribution: Vec<f64>,
+    pub stats: DistributionStats,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NpsOptions {
+    rate: f64,
+    exclude_breaks: bool,
+    slider_weight: f64,
+    spinner_weight: f64,
+}
+
+impl Default for NpsOptions {
+    fn default() -> Self {
+        Self {
+            rate: 1.0,
+            exclude_breaks: false,
+            slider_weight: 1.0,
+            spinner_weight: 1.0,
+        }
+    }
+}
+
+impl NpsOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn rate(mut self, rate: f64) -> Self {
+        self.rate = rate;
+        self
+    }
+
+    pub fn rate_mod(self, rate: RateMod) -> Self {
+        self.rate(rate.multiplier())
+    }
+
+    pub fn exclude_breaks(mut self, exclude_breaks: bool) -> Self {
+        self.exclude_breaks = exclude_breaks;
+        self
+    }
+
+    pub fn slider_weight(mut self, slider_weight: f64) -> Self {
+        self.slider_weight = slider_weight;
+        self
+    }
+
+    pub fn spinner_weight(mut self, spinner_weight: f64) -> Self {
+        self.spinner_weight = spinner_weight;
+        self
+    }
+}
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RateMod {
+    DoubleTime,
+    HalfTime,
+    Custom(f64),
+}
+
+impl RateMod {
+    pub fn multiplier(self) -> f64 {
+        match self {
+            RateMod::DoubleTime => 1.5,
+            RateMod::HalfTime => 0.75,
+            RateMod::Custom(rate) => rate,
+        }
+    }
+}
+
+// Si tous les objets partagent un instant (accord ou note unique), la durée est nulle
+// et on renvoie le nombre d'objets, comme s'ils tenaient sur une seconde
 pub fn calc_nps(map: &Beatmap) -> Option<f64> {
-    let drain_time_ms = map.hit_objects.last()?.start_time - map.hit_objects.first()?.start_time;
+    calc_nps_scaled(map, 1.0)
+}
+
+pub fn calc_nps_with_rate(map: &Beatmap, rate: RateMod) -> Option<f64> {
+    let rate = rate.multiplier();
+    if !rate.is_finite() || rate <= 0.0 {
+        return None;
+    }
+    calc_nps_scaled(map, rate)
+}
+
+// Un rate > 1 compresse les écarts entre notes, donc la durée effective
+fn calc_nps_scaled(map: &Beatmap, rate: f64) -> Option<f64> {
+    let drain_time_ms = (map.hit_objects.last()?.start_time - map.hit_objects.first()?.start_time) / rate;
     if drain_time_ms <= 0.0 {
         return Some(map.hit_objects.len() as f64);
     }
     Some(map.hit_objects.len() as f64 / (drain_time_ms / MS_TO_SEC))
 }
 
+// Intervalle semi-ouvert [start_time, end_time) : deux plages adjacentes ne comptent
+// jamais deux fois la même note
 pub fn calc_nps_range_by_time(map: &Beatmap, start_time: f64, end_time: f64) -> Option<f64> {
     let drain_time_ms = end_time - start_time;
     if drain_time_ms <= 0.0 {
@@ -18,7 +211,7 @@ pub fn calc_nps_range_by_time(map: &Beatmap, start_time: f64, end_time: f64) ->
     }
     
     let start_idx = map.hit_objects.partition_point(|h| h.start_time < start_time);
-    let end_idx = map.hit_objects.partition_point(|h| h.start_time <= end_time);
+    let end_idx = map.hit_objects.partition_point(|h| h.start_time < end_time);
     
     let count = end_idx.saturating_sub(start_idx);
     Some(count as f64 / (drain_time_ms / MS_TO_SEC))
@@ -26,41 +219,93 @@ pub fn calc_nps_range_by_time(map: &Beatmap, start_time: f64, end_time: f64) ->
 
 pub fn calc_nps_range_by_hitobjects(
     map: &Beatmap, 
-    start_obj: &rosu_map::section::hit_objects::HitObject, 
-    end_obj: &rosu_map::section::hit_objects::HitObject
+    start_obj: &HitObject, 
+    end_obj: &HitObject
 ) -> Option<f64> {
-    let drain_time_ms = end_obj.start_time - start_obj.start_time;
+    // Objets introuvables dans map.hit_objects => None, avant tout calcul de durée
+    let start_idx = hit_object_index(map, start_obj)?;
+    let end_idx = hit_object_index(map, end_obj)?;
+    
+    let (start_idx, end_idx) = if start_idx <= end_idx {
+        (start_idx, end_idx)
+    } else {
+        (end_idx, start_idx)
+    };
+    
+    let drain_time_ms = map.hit_objects[end_idx].start_time - map.hit_objects[start_idx].start_time;
     if drain_time_ms <= 0.0 {
         return Some(0.0);
     }
     
-    // Calculate indices using pointer arithmetic - O(1) - PS : VIVA C
-    let slice_ptr = map.hit_objects.as_ptr();
-    let start_ptr = start_obj as *const _ as *const rosu_map::section::hit_objects::HitObject;
-    let end_ptr = end_obj as *const _ as *const rosu_map::section::hit_objects::HitObject;
+    let count = end_idx + 1 - start_idx;
+    Some(count as f64 / (drain_time_ms / MS_TO_SEC))
+}
+
+// Recherche par adresse : un objet égal mais venant d'une autre map n'est pas accepté
+fn hit_object_index(map: &Beatmap, obj: &HitObject) -> Option<usize> {
+    let lower = map.hit_objects.partition_point(|h| h.start_time < obj.start_time);
+    let upper = map.hit_objects.partition_point(|h| h.start_time <= obj.start_time);
+    let candidates = map.hit_objects.get(lower..upper)?;
     
-    if start_ptr < slice_ptr || end_ptr < slice_ptr {
+    candidates
+        .iter()
+        .position(|h| std::ptr::eq(h, obj))
+        .map(|offset| lower + offset)
+}
+
+pub fn calc_distribution(map: &Beatmap, t_parts: i32) -> Option<Vec<f64>> {
+    if t_parts <= 0 || map.hit_objects.is_empty() {
         return None;
     }
+
+    let first_time = map.hit_objects.first()?.start_time;
+    let last_time = map.hit_objects.last()?.start_time;
+    let total_duration_ms = last_time - first_time;
     
-    let start_idx = unsafe { start_ptr.offset_from(slice_ptr) as usize };
-    let end_idx = unsafe { end_ptr.offset_from(slice_ptr) as usize };
-    
-    if start_idx >= map.hit_objects.len() || end_idx >= map.hit_objects.len() {
-        return None;
+    if total_duration_ms <= 0.0 {
+        return Some(vec![0.0; t_parts as usize]);
     }
-    
-    let (start_idx, end_idx) = if start_idx <= end_idx {
-        (start_idx, end_idx + 1)
+
+    let part_duration_ms = total_duration_ms / t_parts as f64;
+    let part_duration_sec = part_duration_ms / MS_TO_SEC;
+
+    Some(
+        part_windows(&map.hit_objects, part_duration_ms, t_parts as usize)
+            .map(|(_, count)| count as f64 / part_duration_sec)
+            .collect(),
+    )
+}
+
+// Nombre de notes de la partie `part` par recherche binaire, avec les mêmes bornes
+// window_start que part_windows pour que l'accès aléatoire donne les mêmes comptes
+#[cfg(feature = "rayon")]
+fn distribution_part_count(map: &Beatmap, first_time: f64, part_duration_ms: f64, part: usize, part_size: usize) -> usize {
+    let start_idx = map.hit_objects.partition_point(|h| h.start_time < window_start(first_time, part_duration_ms, part));
+    let end_idx = if part == part_size - 1 {
+        map.hit_objects.len()
     } else {
-        (end_idx, start_idx + 1)
+        map.hit_objects.partition_point(|h| h.start_time < window_start(first_time, part_duration_ms, part + 1))
     };
-    
-    let count = end_idx - start_idx;
-    Some(count as f64 / (drain_time_ms / MS_TO_SEC))
+
+    end_idx.saturating_sub(start_idx)
 }
 
-pub fn calc_distribution(map: &Beatmap, t_parts: i32) -> Option<Vec<f64>> {
+// Partie contenant `time` pour des parties [window_start(i), window_start(i + 1)),
+// la dernière inclut la dernière note. L'estimation par division peut tomber d'un
+// cran à côté sur une borne, on la corrige avec les bornes exactes.
+fn part_index(time: f64, first_time: f64, part_duration_ms: f64, part_size: usize) -> usize {
+    let mut index = (((time - first_time) / part_duration_ms).max(0.0) as usize).min(part_size - 1);
+    while index > 0 && time < window_start(first_time, part_duration_ms, index) {
+        index -= 1;
+    }
+    while index + 1 < part_size && time >= window_start(first_time, part_duration_ms, index + 1) {
+        index += 1;
+    }
+    index
+}
+
+#[cfg(feature = "rayon")]
+pub fn calc_distribution_parallel(map: &Beatmap, t_parts: i32) -> Option<Vec<f64>> {
     if t_parts <= 0 || map.hit_objects.is_empty() {
         return None;
     }
@@ -68,33 +313,22 @@ pub fn calc_distribution(map: &Beatmap, t_parts: i32) -> Option<Vec<f64>> {
     let first_time = map.hit_objects.first()?.start_time;
     let last_time = map.hit_objects.last()?.start_time;
     let total_duration_ms = last_time - first_time;
-    
+
     if total_duration_ms <= 0.0 {
         return Some(vec![0.0; t_parts as usize]);
     }
 
     let part_duration_ms = total_duration_ms / t_parts as f64;
     let part_duration_sec = part_duration_ms / MS_TO_SEC;
-    let mut distribution = vec![0.0; t_parts as usize];
-    
-    for part in 0..t_parts {
-        let part_start_time = first_time + part as f64 * part_duration_ms;
-        let part_end_time = first_time + (part + 1) as f64 * part_duration_ms;
-        
-        // Utiliser < pour start_time et <= pour end_time pour correspondre à calculate_distribution_old
-        let start_idx = map.hit_objects.partition_point(|h| h.start_time < part_start_time);
-        
-        // Pour la dernière partie, inclure la dernière note avec <=
-        let end_idx = if part == t_parts - 1 {
-            map.hit_objects.partition_point(|h| h.start_time <= part_end_time)
-        } else {
-            map.hit_objects.partition_point(|h| h.start_time < part_end_time)
-        };
-        
-        let count = end_idx.saturating_sub(start_idx);
-        distribution[part as usize] = count as f64 / part_duration_sec;
-    }
-    
+    let part_size = t_parts as usize;
+    let mut distribution = Vec::with_capacity(part_size);
+
+    // Mêmes bornes et même division que calc_distribution, donc résultats identiques au bit près
+    (0..part_size)
+        .into_par_iter()
+        .map(|part| distribution_part_count(map, first_time, part_duration_ms, part, part_size) as f64 / part_duration_sec)
+        .collect_into_vec(&mut distribution);
+
     Some(distribution)
 }
 
@@ -102,7 +336,9 @@ pub fn to_sec(ms: f64) -> f64 {
     ms / MS_TO_SEC
 }
 
-pub fn calc_distribution_2(map: &Beatmap, t_parts: i32) -> Option<Vec<f64>> {
+// Même découpage que calc_distribution. Si tous les objets partagent un instant ils
+// tombent dans la première partie : la somme vaut toujours hit_objects.len()
+pub fn calc_count_distribution(map: &Beatmap, t_parts: i32) -> Option<Vec<usize>> {
     if t_parts <= 0 || map.hit_objects.is_empty() {
         return None;
     }
@@ -110,18 +346,32 @@ pub fn calc_distribution_2(map: &Beatmap, t_parts: i32) -> Option<Vec<f64>> {
     let first_time = map.hit_objects.first()?.start_time;
     let last_time = map.hit_objects.last()?.start_time;
     let total_duration_ms = last_time - first_time;
-    let part_duration_ms = total_duration_ms / t_parts as f64;
     let part_size = t_parts as usize;
-    let mut counts = vec![0usize; part_size];
-    let inv_part_duration = 1.0 / part_duration_ms;
-    
-    for hit_object in &map.hit_objects {
-        let relative_time = hit_object.start_time - first_time;
-        let index = ((relative_time * inv_part_duration) as usize).min(part_size - 1);
-        counts[index] += 1;
+    let mut counts = vec![0; part_size];
+
+    if total_duration_ms <= 0.0 {
+        counts[0] = map.hit_objects.len();
+        return Some(counts);
     }
 
-    let part_duration_sec = to_sec(part_duration_ms);
+    let part_duration_ms = total_duration_ms / t_parts as f64;
+    for h in &map.hit_objects {
+        counts[part_index(h.start_time, first_time, part_duration_ms, part_size)] += 1;
+    }
+
+    Some(counts)
+}
+
+pub fn calc_distribution_2(map: &Beatmap, t_parts: i32) -> Option<Vec<f64>> {
+    let counts = calc_count_distribution(map, t_parts)?;
+    let total_duration_ms = drain_time_ms(map)?;
+
+    // Durée nulle : les notes sont toutes dans la première partie mais la densité reste 0
+    if total_duration_ms <= 0.0 {
+        return Some(vec![0.0; counts.len()]);
+    }
+
+    let part_duration_sec = to_sec(total_duration_ms / t_parts as f64);
 
     Some(
         counts
@@ -146,7 +396,2678 @@ pub fn calc_distribution_smart(map: &Beatmap, t_parts: i32) -> Option<Vec<f64>>
         // Pour beaucoup de parties, l'algorithme old est plus efficace
         calc_distribution_2(map, t_parts)
     } else {
-        // Pour peu de parties, l'algorithme new avec binary search est plus efficace
+        // Pour peu de parties, les fenêtres à deux pointeurs de calc_distribution suffisent
         calc_distribution(map, t_parts)
     }
-}
\ No newline at end of file
+}
+
+pub fn calc_peak_nps(map: &Beatmap, window_ms: f64) -> Option<f64> {
+    calc_peak_nps_at(map, window_ms).map(|(_, nps)| nps)
+}
+
+pub fn calc_peak_nps_at(map: &Beatmap, window_ms: f64) -> Option<(f64, f64)> {
+    if !is_window(window_ms) || map.hit_objects.is_empty() {
+        return None;
+    }
+
+    let (peak_start, max_count) = first_best_window(anchored_windows(&map.hit_objects, window_ms), |a, b| a > b)?;
+    Some((peak_start, max_count as f64 / to_sec(window_ms)))
+}
+
+// Fenêtre utilisable : finie et strictement positive, NaN est donc rejeté
+fn is_window(window_ms: f64) -> bool {
+    window_ms.is_finite() && window_ms > 0.0
+}
+
+// Garde la fenêtre dont le compte bat strictement les précédentes selon `better` :
+// en cas d'égalité c'est la plus tôt qui reste
+fn first_best_window<I, F>(windows: I, better: F) -> Option<(f64, usize)>
+where
+    I: Iterator<Item = (f64, usize)>,
+    F: Fn(usize, usize) -> bool,
+{
+    windows.fold(None, |best, curr| match best {
+        Some(best) if !better(curr.1, best.1) => Some(best),
+        _ => Some(curr),
+    })
+}
+
+fn hit_object_x(h: &HitObject) -> f32 {
+    hit_object_pos(h).x
+}
+
+// Les holds mania n'ont qu'une position x, on les place au centre vertical
+fn hit_object_pos(h: &HitObject) -> Pos {
+    match &h.kind {
+        HitObjectKind::Circle(c) => c.pos,
+        HitObjectKind::Slider(s) => s.pos,
+        HitObjectKind::Spinner(s) => s.pos,
+        HitObjectKind::Hold(h) => Pos::new(h.pos_x, PLAYFIELD_HEIGHT / 2.0),
+    }
+}
+
+pub fn calc_nps_per_column(map: &Beatmap) -> Option<Vec<f64>> {
+    if map.mode != GameMode::Mania || map.hit_objects.is_empty() {
+        return None;
+    }
+
+    // En mania, CircleSize correspond au nombre de touches
+    let key_count = map.circle_size.round() as usize;
+    if key_count == 0 {
+        return None;
+    }
+
+    let mut counts = vec![0usize; key_count];
+    for h in &map.hit_objects {
+        let column = (hit_object_x(h) * key_count as f32 / PLAYFIELD_WIDTH).floor() as usize;
+        counts[column.min(key_count - 1)] += 1;
+    }
+
+    let drain_time_ms = map.hit_objects.last()?.start_time - map.hit_objects.first()?.start_time;
+    if drain_time_ms <= 0.0 {
+        return Some(counts.into_iter().map(|count| count as f64).collect());
+    }
+
+    let drain_time_sec = to_sec(drain_time_ms);
+    Some(counts.into_iter().map(|count| count as f64 / drain_time_sec).collect())
+}
+
+pub fn calc_nps_series(map: &Beatmap, interval_ms: f64) -> Option<Vec<KeyValue>> {
+    if !is_key_interval(interval_ms) || map.hit_objects.is_empty() {
+        return None;
+    }
+
+    Some(nps_intervals(map, interval_ms).collect())
+}
+
+// Vide si l'intervalle ne peut pas servir de clé, comme calc_nps_series renvoie None
+pub fn nps_intervals<'a>(map: &'a Beatmap, interval_ms: f64) -> impl Iterator<Item = KeyValue> + 'a {
+    let interval_sec = to_sec(interval_ms);
+
+    is_key_interval(interval_ms)
+        .then(|| windows_by_time(&map.hit_objects, interval_ms))
+        .into_iter()
+        .flatten()
+        .map(move |(window_start, count)| KeyValue {
+            key: window_start as i32,
+            value: count as f64 / interval_sec,
+        })
+}
+
+// Les clés KeyValue sont des ms entières : sous 1ms plusieurs fenêtres auraient la même clé
+fn is_key_interval(interval_ms: f64) -> bool {
+    interval_ms.is_finite() && interval_ms >= 1.0
+}
+
+pub fn calc_nps_series_from_times(times: &[f64], interval_ms: f64) -> Result<Vec<KeyValue>, CalcError> {
+    if !interval_ms.is_finite() || interval_ms <= 0.0 {
+        return Err(CalcError::InvalidDuration);
+    }
+    if !is_key_interval(interval_ms) {
+        return Err(CalcError::IntervalTooSmall);
+    }
+    if times.is_empty() {
+        return Err(CalcError::EmptyMap);
+    }
+    // Hors de [0, i32::MAX] la clé `as i32` serait saturée
+    if times.iter().any(|&t| !(0.0..=i32::MAX as f64).contains(&t)) {
+        return Err(CalcError::TimingOutOfRange);
+    }
+    if !times.is_sorted() {
+        return Err(CalcError::UnsortedTimings);
+    }
+
+    let interval_sec = to_sec(interval_ms);
+    Ok(windows_by_time(times, interval_ms)
+        .map(|(window_start, count)| KeyValue {
+            key: window_start as i32,
+            value: count as f64 / interval_sec,
+        })
+        .collect())
+}
+
+pub fn time_windows(map: &Beatmap, window_ms: f64) -> Vec<(f64, usize)> {
+    windows_by_time(&map.hit_objects, window_ms).collect()
+}
+
+pub(crate) trait StartTime {
+    fn start_time(&self) -> f64;
+}
+
+impl StartTime for f64 {
+    fn start_time(&self) -> f64 {
+        *self
+    }
+}
+
+impl StartTime for HitObject {
+    fn start_time(&self) -> f64 {
+        self.start_time
+    }
+}
+
+// Compte les notes de fenêtres [start, end) par deux pointeurs : les bornes passées
+// à `count` doivent être croissantes d'un appel à l'autre
+#[derive(Default)]
+struct WindowCounter {
+    start_idx: usize,
+    end_idx: usize,
+}
+
+impl WindowCounter {
+    fn count<T: StartTime>(&mut self, times: &[T], start: f64, end: f64) -> usize {
+        while self.start_idx < times.len() && times[self.start_idx].start_time() < start {
+            self.start_idx += 1;
+        }
+        while self.end_idx < times.len() && times[self.end_idx].start_time() < end {
+            self.end_idx += 1;
+        }
+        self.end_idx.saturating_sub(self.start_idx)
+    }
+}
+
+// La première fenêtre part de la note même pour un pas infini (0 * INFINITY vaut NaN)
+pub(crate) fn window_start(first_time: f64, step_ms: f64, index: usize) -> f64 {
+    if index == 0 {
+        return first_time;
+    }
+    first_time + index as f64 * step_ms
+}
+
+// Fenêtres [start, start + window_ms) partant de la première note et avançant de step_ms
+pub(crate) struct TimeWindows<'a, T> {
+    times: &'a [T],
+    window_ms: f64,
+    step_ms: f64,
+    n_windows: usize,
+    index: usize,
+    counter: WindowCounter,
+}
+
+impl<T: StartTime> Iterator for TimeWindows<'_, T> {
+    type Item = (f64, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.index >= self.n_windows {
+            return None;
+        }
+
+        let first_time = self.times[0].start_time();
+        let start = window_start(first_time, self.step_ms, self.index);
+        // Si les fenêtres se recouvrent ou se touchent, la dernière contient forcément
+        // la dernière note : on l'ouvre à droite pour ne pas la perdre sur un arrondi.
+        // Des fenêtres contiguës finissent au début exact de la suivante.
+        let end = if self.index == self.n_windows - 1 && self.window_ms >= self.step_ms {
+            f64::INFINITY
+        } else if self.window_ms == self.step_ms {
+            window_start(first_time, self.step_ms, self.index + 1)
+        } else {
+            start + self.window_ms
+        };
+
+        self.index += 1;
+        Some((start, self.counter.count(self.times, start, end)))
+    }
+}
+
+pub(crate) fn windows_by_time<T: StartTime>(times: &[T], window_ms: f64) -> TimeWindows<'_, T> {
+    sliding_windows_by_time(times, window_ms, window_ms)
+}
+
+pub(crate) fn sliding_windows_by_time<T: StartTime>(times: &[T], window_ms: f64, step_ms: f64) -> TimeWindows<'_, T> {
+    let n_windows = match (times.first(), times.last()) {
+        (Some(first), Some(last)) if window_ms > 0.0 && step_ms > 0.0 => {
+            ((last.start_time() - first.start_time()) / step_ms) as usize + 1
+        }
+        _ => 0,
+    };
+
+    TimeWindows {
+        times,
+        window_ms,
+        step_ms,
+        n_windows,
+        index: 0,
+        counter: WindowCounter::default(),
+    }
+}
+
+// Exactement n_parts fenêtres contiguës, la dernière ouverte à droite
+fn part_windows<T: StartTime>(times: &[T], part_duration_ms: f64, n_parts: usize) -> TimeWindows<'_, T> {
+    TimeWindows {
+        times,
+        window_ms: part_duration_ms,
+        step_ms: part_duration_ms,
+        n_windows: if times.is_empty() { 0 } else { n_parts },
+        index: 0,
+        counter: WindowCounter::default(),
+    }
+}
+
+// Fenêtres [t, t + window_ms) ancrées sur chaque note, y compris les dernières dont la
+// fenêtre dépasse la fin de la map. Les notes empilées au même instant ne produisent
+// qu'une seule fenêtre.
+pub(crate) struct AnchoredWindows<'a, T> {
+    times: &'a [T],
+    window_ms: f64,
+    next_idx: usize,
+    counter: WindowCounter,
+}
+
+impl<T: StartTime> Iterator for AnchoredWindows<'_, T> {
+    type Item = (f64, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = self.times.get(self.next_idx)?.start_time();
+        while self.next_idx < self.times.len() && self.times[self.next_idx].start_time() == start {
+            self.next_idx += 1;
+        }
+
+        Some((start, self.counter.count(self.times, start, start + self.window_ms)))
+    }
+}
+
+pub(crate) fn anchored_windows<T: StartTime>(times: &[T], window_ms: f64) -> AnchoredWindows<'_, T> {
+    AnchoredWindows {
+        times,
+        window_ms,
+        next_idx: 0,
+        counter: WindowCounter::default(),
+    }
+}
+
+pub fn smooth_distribution(values: &[f64], window: usize) -> Vec<f64> {
+    if window <= 1 {
+        return values.to_vec();
+    }
+
+    // Sommes préfixées pour une moyenne glissante en O(n)
+    let mut prefix = Vec::with_capacity(values.len() + 1);
+    prefix.push(0.0);
+    for &value in values {
+        prefix.push(prefix.last().unwrap() + value);
+    }
+
+    // Fenêtre centrée, tronquée aux bords pour garder la même longueur
+    let left = (window - 1) / 2;
+    let right = window / 2;
+
+    (0..values.len())
+        .map(|i| {
+            let start = i.saturating_sub(left);
+            let end = (i + right + 1).min(values.len());
+            (prefix[end] - prefix[start]) / (end - start) as f64
+        })
+        .collect()
+}
+
+pub fn calc_nps_weighted(map: &Beatmap, slider_weight: f64, spinner_weight: f64) -> Option<f64> {
+    let drain_time_ms = map.hit_objects.last()?.start_time - map.hit_objects.first()?.start_time;
+
+    let weighted_count: f64 = map
+        .hit_objects
+        .iter()
+        .map(|h| object_weight(h, slider_weight, spinner_weight))
+        .sum();
+
+    if drain_time_ms <= 0.0 {
+        return Some(weighted_count);
+    }
+    Some(weighted_count / to_sec(drain_time_ms))
+}
+
+fn object_weight(h: &HitObject, slider_weight: f64, spinner_weight: f64) -> f64 {
+    // Les holds mania sont des notes tenues, comme les sliders
+    match h.kind {
+        HitObjectKind::Circle(_) => 1.0,
+        HitObjectKind::Slider(_) | HitObjectKind::Hold(_) => slider_weight,
+        HitObjectKind::Spinner(_) => spinner_weight,
+    }
+}
+
+pub fn calc_nps_drain(map: &Beatmap) -> Option<f64> {
+    let first_time = map.hit_objects.first()?.start_time;
+    let last_time = map.hit_objects.last()?.start_time;
+
+    let drain_time_ms = last_time - first_time - break_time_ms(map, first_time, last_time);
+    if drain_time_ms <= 0.0 {
+        return Some(map.hit_objects.len() as f64);
+    }
+    Some(map.hit_objects.len() as f64 / to_sec(drain_time_ms))
+}
+
+fn break_time_ms(map: &Beatmap, first_time: f64, last_time: f64) -> f64 {
+    map.breaks
+        .iter()
+        .map(|b| clipped_duration_ms(b.start_time, b.end_time, first_time, last_time))
+        .sum()
+}
+
+// Seule la partie de [start, end) comprise entre la première et la dernière note compte
+fn clipped_duration_ms(start: f64, end: f64, first_time: f64, last_time: f64) -> f64 {
+    (end.min(last_time) - start.max(first_time)).max(0.0)
+}
+
+pub fn distribution_stats(values: &[f64]) -> Option<DistributionStats> {
+    if values.is_empty() {
+        return None;
+    }
+
+    let len = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / len;
+    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / len;
+
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+
+    let mid = sorted.len() / 2;
+    let median = if sorted.len().is_multiple_of(2) {
+        (sorted[mid - 1] + sorted[mid]) / 2.0
+    } else {
+        sorted[mid]
+    };
+
+    Some(DistributionStats {
+        mean,
+        median,
+        std_dev: variance.sqrt(),
+        max: sorted[sorted.len() - 1],
+        min: sorted[0],
+    })
+}
+
+pub fn find_streams(map: &Beatmap, min_nps: f64, min_duration_ms: f64) -> Vec<(f64, f64)> {
+    dense_runs(map, min_nps)
+        .into_iter()
+        .map(|(start_idx, end_idx)| (map.hit_objects[start_idx].start_time, map.hit_objects[end_idx].start_time))
+        .filter(|&(start_time, end_time)| end_time - start_time >= min_duration_ms)
+        .collect()
+}
+
+pub fn find_bursts(map: &Beatmap, min_notes: usize, max_notes: usize, min_nps: f64) -> Vec<(f64, f64)> {
+    dense_runs(map, min_nps)
+        .into_iter()
+        .filter(|&(start_idx, end_idx)| (min_notes..=max_notes).contains(&(end_idx - start_idx + 1)))
+        .map(|(start_idx, end_idx)| (map.hit_objects[start_idx].start_time, map.hit_objects[end_idx].start_time))
+        .collect()
+}
+
+// Plages d'index inclusives d'au moins deux notes consécutives dont chaque écart
+// respecte min_nps. NPS locale entre deux notes = 1 / écart, donc seuil sur l'écart.
+fn dense_runs(map: &Beatmap, min_nps: f64) -> Vec<(usize, usize)> {
+    let mut runs = Vec::new();
+    if min_nps <= 0.0 || map.hit_objects.len() < 2 {
+        return runs;
+    }
+
+    let max_gap_ms = MS_TO_SEC / min_nps;
+    let objects = &map.hit_objects;
+    let mut run_start = 0;
+
+    for i in 1..=objects.len() {
+        let in_run = i < objects.len() && objects[i].start_time - objects[i - 1].start_time <= max_gap_ms;
+        if in_run {
+            continue;
+        }
+
+        if i - 1 > run_start {
+            runs.push((run_start, i - 1));
+        }
+        run_start = i;
+    }
+
+    runs
+}
+
+pub fn calc_nps_from_bytes(data: &[u8]) -> Result<f64, CalcError> {
+    let map = Beatmap::from_bytes(data).map_err(|_| CalcError::ParseFailed)?;
+    calc_nps(&map).ok_or(CalcError::EmptyMap)
+}
+
+// Beat length (ms) du point de timing non hérité qui couvre le plus de temps.
+// Le premier point couvre le début de la map, le dernier va jusqu'à la dernière note.
+fn primary_beat_len(map: &Beatmap) -> Option<f64> {
+    let timing_points = &map.control_points.timing_points;
+    let first_time = map.hit_objects.first().map_or(timing_points.first()?.time, |h| h.start_time);
+    let last_time = map.hit_objects.last().map_or(timing_points.last()?.time, |h| h.start_time);
+
+    let mut best: Option<(f64, f64)> = None;
+    for (i, point) in timing_points.iter().enumerate() {
+        let start = if i == 0 { first_time } else { point.time.max(first_time) };
+        let end = timing_points.get(i + 1).map_or(last_time, |next| next.time.min(last_time));
+        let span = (end - start).max(0.0);
+
+        if best.is_none_or(|(best_span, _)| span > best_span) {
+            best = Some((span, point.beat_len));
+        }
+    }
+
+    best.map(|(_, beat_len)| beat_len).filter(|&beat_len| beat_len > 0.0)
+}
+
+pub fn primary_bpm(map: &Beatmap) -> Option<f64> {
+    Some(MS_PER_MINUTE / primary_beat_len(map)?)
+}
+
+pub fn bpm_range(map: &Beatmap) -> Option<(f64, f64)> {
+    map.control_points
+        .timing_points
+        .iter()
+        .filter(|point| point.beat_len > 0.0)
+        .map(|point| MS_PER_MINUTE / point.beat_len)
+        .fold(None, |range, bpm| match range {
+            Some((min, max)) => Some((bpm.min(min), bpm.max(max))),
+            None => Some((bpm, bpm)),
+        })
+}
+
+pub fn calc_notes_per_beat(map: &Beatmap) -> Option<f64> {
+    let beats_per_sec = primary_bpm(map)? / 60.0;
+    Some(calc_nps(map)? / beats_per_sec)
+}
+
+pub fn interval_histogram(map: &Beatmap, bucket_ms: f64) -> Option<Vec<(f64, usize)>> {
+    if bucket_ms <= 0.0 || map.hit_objects.len() < 2 {
+        return None;
+    }
+
+    // Seuls les buckets non vides sont renvoyés, triés par borne inférieure
+    let mut buckets = BTreeMap::new();
+    for pair in map.hit_objects.windows(2) {
+        let gap = (pair[1].start_time - pair[0].start_time).max(0.0);
+        *buckets.entry((gap / bucket_ms) as usize).or_insert(0usize) += 1;
+    }
+
+    Some(
+        buckets
+            .into_iter()
+            .map(|(bucket, count)| (bucket as f64 * bucket_ms, count))
+            .collect(),
+    )
+}
+
+pub fn calc_nps_kiai(map: &Beatmap) -> Option<f64> {
+    let last_time = map.hit_objects.last()?.start_time;
+    let effect_points = &map.control_points.effect_points;
+
+    let mut has_kiai = false;
+    let mut kiai_time_ms = 0.0;
+    let mut count = 0;
+
+    // Chaque point kiai reste actif jusqu'au point d'effet suivant
+    for (i, point) in effect_points.iter().enumerate() {
+        if !point.kiai {
+            continue;
+        }
+        has_kiai = true;
+
+        let end_time = effect_points.get(i + 1).map_or(f64::INFINITY, |next| next.time);
+        let start_idx = map.hit_objects.partition_point(|h| h.start_time < point.time);
+        let end_idx = map.hit_objects.partition_point(|h| h.start_time < end_time);
+
+        count += end_idx.saturating_sub(start_idx);
+        kiai_time_ms += (end_time.min(last_time) - point.time).max(0.0);
+    }
+
+    if !has_kiai {
+        return None;
+    }
+    if kiai_time_ms <= 0.0 {
+        return Some(count as f64);
+    }
+    Some(count as f64 / to_sec(kiai_time_ms))
+}
+
+pub fn calc_type_distribution(map: &Beatmap, t_parts: i32) -> Option<Vec<[f64; 3]>> {
+    if t_parts <= 0 || map.hit_objects.is_empty() {
+        return None;
+    }
+
+    let first_time = map.hit_objects.first()?.start_time;
+    let last_time = map.hit_objects.last()?.start_time;
+    let total_duration_ms = last_time - first_time;
+    let part_size = t_parts as usize;
+
+    if total_duration_ms <= 0.0 {
+        return Some(vec![[0.0; 3]; part_size]);
+    }
+
+    let part_duration_ms = total_duration_ms / t_parts as f64;
+    let mut counts = vec![[0usize; 3]; part_size];
+
+    for hit_object in &map.hit_objects {
+        let index = part_index(hit_object.start_time, first_time, part_duration_ms, part_size);
+
+        // [circle, slider, spinner], les holds mania comptent comme des sliders
+        let lane = match hit_object.kind {
+            HitObjectKind::Circle(_) => 0,
+            HitObjectKind::Slider(_) | HitObjectKind::Hold(_) => 1,
+            HitObjectKind::Spinner(_) => 2,
+        };
+        counts[index][lane] += 1;
+    }
+
+    let part_duration_sec = to_sec(part_duration_ms);
+
+    Some(
+        counts
+            .into_iter()
+            .map(|lanes| lanes.map(|count| count as f64 / part_duration_sec))
+            .collect(),
+    )
+}
+
+pub fn calc_nps_with_options(map: &Beatmap, opts: &NpsOptions) -> Option<f64> {
+    if !opts.rate.is_finite() || opts.rate <= 0.0 {
+        return None;
+    }
+
+    let first_time = map.hit_objects.first()?.start_time;
+    let last_time = map.hit_objects.last()?.start_time;
+
+    let weighted_count: f64 = map
+        .hit_objects
+        .iter()
+        .map(|h| object_weight(h, opts.slider_weight, opts.spinner_weight))
+        .sum();
+
+    let mut drain_time_ms = last_time - first_time;
+    if opts.exclude_breaks {
+        drain_time_ms -= break_time_ms(map, first_time, last_time);
+    }
+    drain_time_ms /= opts.rate;
+
+    if drain_time_ms <= 0.0 {
+        return Some(weighted_count);
+    }
+    Some(weighted_count / to_sec(drain_time_ms))
+}
+
+pub fn aggregate_peak_nps(map: &Beatmap, window_ms: f64, decay: f64) -> Option<f64> {
+    // contains() rejette aussi NaN
+    if !is_window(window_ms) || !(0.0..=1.0).contains(&decay) || map.hit_objects.is_empty() {
+        return None;
+    }
+
+    let window_sec = to_sec(window_ms);
+    let mut peaks: Vec<f64> = anchored_windows(&map.hit_objects, window_ms)
+        .map(|(_, count)| count as f64 / window_sec)
+        .collect();
+    peaks.sort_by(|a, b| b.total_cmp(a));
+
+    // Pondération géométrique decay^i, le poids du pic vaut 0f64.powi(0) == 1 : avec
+    // decay = 0 seul le pic compte
+    let mut weight = 1.0;
+    let mut total = 0.0;
+    for peak in peaks {
+        total += peak * weight;
+        weight *= decay;
+        if weight == 0.0 {
+            break;
+        }
+    }
+
+    Some(total)
+}
+
+pub fn calc_nps_per_second(map: &Beatmap) -> Option<Vec<f64>> {
+    let last_time = map.hit_objects.last()?.start_time;
+
+    // Secondes entières depuis 0ms, les notes avant 0ms tombent dans la première
+    let n_seconds = (last_time.max(0.0) / MS_TO_SEC) as usize + 1;
+    let mut counts = vec![0usize; n_seconds];
+
+    for hit_object in &map.hit_objects {
+        let second = (hit_object.start_time.max(0.0) / MS_TO_SEC) as usize;
+        counts[second.min(n_seconds - 1)] += 1;
+    }
+
+    Some(counts.into_iter().map(|count| count as f64).collect())
+}
+
+pub fn calc_nps_range_by_index(map: &Beatmap, start_idx: usize, end_idx: usize) -> Option<f64> {
+    if start_idx > end_idx || end_idx >= map.hit_objects.len() {
+        return None;
+    }
+
+    let drain_time_ms = map.hit_objects[end_idx].start_time - map.hit_objects[start_idx].start_time;
+    if drain_time_ms <= 0.0 {
+        return Some(0.0);
+    }
+
+    // Intervalle inclusif [start_idx, end_idx]
+    let count = end_idx - start_idx + 1;
+    Some(count as f64 / to_sec(drain_time_ms))
+}
+
+pub fn find_local_maxima(values: &[f64], min_prominence: f64) -> Vec<usize> {
+    let mut maxima = Vec::new();
+    let mut i = 1;
+
+    // Les bords n'ont qu'un voisin et ne sont jamais retenus
+    while i + 1 < values.len() {
+        // Un plateau est traité comme un seul point, reporté à son premier index
+        let mut plateau_end = i;
+        while plateau_end + 1 < values.len() && values[plateau_end + 1] == values[i] {
+            plateau_end += 1;
+        }
+
+        if plateau_end + 1 < values.len()
+            && values[i] - values[i - 1] >= min_prominence
+            && values[i] - values[plateau_end + 1] >= min_prominence
+            && values[i] > values[i - 1]
+            && values[i] > values[plateau_end + 1]
+        {
+            maxima.push(i);
+        }
+
+        i = plateau_end + 1;
+    }
+
+    maxima
+}
+
+pub fn calc_nps_total(map: &Beatmap, audio_length_ms: f64) -> Option<f64> {
+    let last_time = map.hit_objects.last()?.start_time;
+    if audio_length_ms <= 0.0 || audio_length_ms < last_time {
+        return None;
+    }
+
+    Some(map.hit_objects.len() as f64 / to_sec(audio_length_ms))
+}
+
+pub fn dominant_snap(map: &Beatmap) -> Option<u32> {
+    if map.control_points.timing_points.is_empty() {
+        return None;
+    }
+
+    let mut counts = [0usize; SNAP_DIVISORS.len()];
+
+    for pair in map.hit_objects.windows(2) {
+        let gap = pair[1].start_time - pair[0].start_time;
+        // Les accords (écart nul) n'ont pas de snap
+        if gap <= 0.0 {
+            continue;
+        }
+
+        let Some(beat_len) = map
+            .control_points
+            .timing_point_at(pair[0].start_time)
+            .map(|point| point.beat_len)
+            .filter(|&beat_len| beat_len > 0.0)
+        else {
+            continue;
+        };
+
+        counts[nearest_snap_index(gap, beat_len)] += 1;
+    }
+
+    // En cas d'égalité le plus petit diviseur l'emporte
+    let (best, &best_count) = counts
+        .iter()
+        .enumerate()
+        .fold((0, &0), |best, curr| if curr.1 > best.1 { curr } else { best });
+
+    (best_count > 0).then_some(SNAP_DIVISORS[best])
+}
+
+// Le plus petit diviseur dont un multiple tombe à SNAP_TOLERANCE_MS près de l'écart,
+// sinon le diviseur le plus proche. Ainsi un 2/8 est classé en 1/4.
+fn nearest_snap_index(gap: f64, beat_len: f64) -> usize {
+    let errors = SNAP_DIVISORS.map(|divisor| {
+        let snap_len = beat_len / divisor as f64;
+        let multiple = (gap / snap_len).round().max(1.0);
+        (gap - multiple * snap_len).abs()
+    });
+
+    errors
+        .iter()
+        .position(|&error| error <= SNAP_TOLERANCE_MS)
+        .unwrap_or_else(|| {
+            errors
+                .iter()
+                .enumerate()
+                .min_by(|a, b| a.1.total_cmp(b.1))
+                .map_or(0, |(i, _)| i)
+        })
+}
+
+pub fn compare_distributions(a: &[f64], b: &[f64]) -> Option<Vec<f64>> {
+    if a.len() != b.len() {
+        return None;
+    }
+
+    Some(a.iter().zip(b).map(|(a, b)| a - b).collect())
+}
+
+pub fn calc_nps_percentile(map: &Beatmap, window_ms: f64, percentile: f64) -> Option<f64> {
+    if !is_window(window_ms) || map.hit_objects.is_empty() || !(0.0..=100.0).contains(&percentile) {
+        return None;
+    }
+
+    let window_sec = to_sec(window_ms);
+    let mut densities: Vec<f64> = anchored_windows(&map.hit_objects, window_ms)
+        .map(|(_, count)| count as f64 / window_sec)
+        .collect();
+    densities.sort_by(f64::total_cmp);
+
+    // Interpolation linéaire entre les deux rangs encadrants
+    let rank = percentile / 100.0 * (densities.len() - 1) as f64;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+    let fraction = rank - lower as f64;
+
+    Some(densities[lower] + (densities[upper] - densities[lower]) * fraction)
+}
+
+pub fn calc_taiko_density(map: &Beatmap) -> Option<(f64, f64)> {
+    if map.mode != GameMode::Taiko || map.hit_objects.is_empty() {
+        return None;
+    }
+
+    // Whistle ou clap => kat, sinon don
+    let kat_count = map
+        .hit_objects
+        .iter()
+        .filter(|h| {
+            h.samples
+                .iter()
+                .any(|s| s.name == HitSampleInfo::HIT_WHISTLE || s.name == HitSampleInfo::HIT_CLAP)
+        })
+        .count();
+    let don_count = map.hit_objects.len() - kat_count;
+
+    let drain_time_ms = map.hit_objects.last()?.start_time - map.hit_objects.first()?.start_time;
+    if drain_time_ms <= 0.0 {
+        return Some((don_count as f64, kat_count as f64));
+    }
+
+    let drain_time_sec = to_sec(drain_time_ms);
+    Some((don_count as f64 / drain_time_sec, kat_count as f64 / drain_time_sec))
+}
+
+pub fn top_n_dense_windows(map: &Beatmap, window_ms: f64, n: usize) -> Vec<(f64, f64)> {
+    if !is_window(window_ms) || n == 0 {
+        return Vec::new();
+    }
+
+    let mut candidates: Vec<(f64, usize)> = anchored_windows(&map.hit_objects, window_ms).collect();
+    // Plus dense d'abord, puis la plus tôt en cas d'égalité
+    candidates.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.total_cmp(&b.0)));
+
+    let window_sec = to_sec(window_ms);
+    let mut selected: Vec<(f64, f64)> = Vec::with_capacity(n);
+
+    for (start_time, count) in candidates {
+        // Deux fenêtres [s, s + window_ms) se chevauchent si leurs débuts sont à moins de window_ms
+        if selected.iter().all(|&(s, _)| (s - start_time).abs() >= window_ms) {
+            selected.push((start_time, count as f64 / window_sec));
+            if selected.len() == n {
+                break;
+            }
+        }
+    }
+
+    selected
+}
+
+pub fn normalize_distribution(values: &[f64]) -> Vec<f64> {
+    // Plancher à zéro : seul le max est ramené à 1.0
+    let max = values.iter().copied().fold(0.0, f64::max);
+    if max <= 0.0 {
+        return vec![0.0; values.len()];
+    }
+
+    values.iter().map(|v| v.max(0.0) / max).collect()
+}
+
+pub fn cumulative_notes(map: &Beatmap, interval_ms: f64) -> Option<Vec<(f64, usize)>> {
+    if interval_ms <= 0.0 {
+        return None;
+    }
+
+    let first_time = map.hit_objects.first()?.start_time;
+    let last_time = map.hit_objects.last()?.start_time;
+    let n_steps = ((last_time - first_time) / interval_ms).ceil() as usize;
+
+    // Nombre de notes avec start_time <= t, la dernière borne est exactement la dernière note
+    let mut points: Vec<(f64, usize)> = (0..n_steps)
+        .map(|i| {
+            let time = first_time + i as f64 * interval_ms;
+            (time, map.hit_objects.partition_point(|h| h.start_time <= time))
+        })
+        .collect();
+    points.push((last_time, map.hit_objects.len()));
+
+    Some(points)
+}
+
+pub fn average_distributions(dists: &[Vec<f64>]) -> Option<Vec<f64>> {
+    let len = dists.first()?.len();
+    if dists.iter().any(|dist| dist.len() != len) {
+        return None;
+    }
+
+    let mut sums = vec![0.0; len];
+    for dist in dists {
+        for (sum, value) in sums.iter_mut().zip(dist) {
+            *sum += value;
+        }
+    }
+
+    let n_dists = dists.len() as f64;
+    Some(sums.into_iter().map(|sum| sum / n_dists).collect())
+}
+
+pub fn playfield_heatmap(map: &Beatmap, grid_w: usize, grid_h: usize) -> Option<Vec<Vec<usize>>> {
+    if grid_w == 0 || grid_h == 0 || map.hit_objects.is_empty() {
+        return None;
+    }
+
+    // heatmap[ligne][colonne], les positions hors playfield sont ramenées au bord
+    let mut heatmap = vec![vec![0usize; grid_w]; grid_h];
+    for h in &map.hit_objects {
+        let pos = hit_object_pos(h);
+        let col = ((pos.x / PLAYFIELD_WIDTH * grid_w as f32).max(0.0) as usize).min(grid_w - 1);
+        let row = ((pos.y / PLAYFIELD_HEIGHT * grid_h as f32).max(0.0) as usize).min(grid_h - 1);
+        heatmap[row][col] += 1;
+    }
+
+    Some(heatmap)
+}
+
+pub fn calc_nps_filtered<F: Fn(&HitObject) -> bool>(map: &Beatmap, predicate: F) -> Option<f64> {
+    let drain_time_ms = map.hit_objects.last()?.start_time - map.hit_objects.first()?.start_time;
+
+    // On filtre le décompte mais la durée reste celle de toute la map
+    let count = map.hit_objects.iter().filter(|h| predicate(h)).count();
+    if drain_time_ms <= 0.0 {
+        return Some(count as f64);
+    }
+    Some(count as f64 / to_sec(drain_time_ms))
+}
+
+pub fn calc_rolling_nps(map: &Beatmap, window_ms: f64, step_ms: f64) -> Option<Vec<KeyValue>> {
+    if !is_window(window_ms) || !is_key_interval(step_ms) || map.hit_objects.is_empty() {
+        return None;
+    }
+
+    let window_sec = to_sec(window_ms);
+
+    Some(
+        sliding_windows_by_time(&map.hit_objects, window_ms, step_ms)
+            .map(|(window_start, count)| KeyValue {
+                key: window_start as i32,
+                value: count as f64 / window_sec,
+            })
+            .collect(),
+    )
+}
+
+pub fn calc_travel_distance(map: &Beatmap, t_parts: i32) -> Option<Vec<f64>> {
+    if map.mode != GameMode::Osu || t_parts <= 0 || map.hit_objects.is_empty() {
+        return None;
+    }
+
+    let first_time = map.hit_objects.first()?.start_time;
+    let last_time = map.hit_objects.last()?.start_time;
+    let total_duration_ms = last_time - first_time;
+    let part_size = t_parts as usize;
+
+    if total_duration_ms <= 0.0 {
+        return Some(vec![0.0; part_size]);
+    }
+
+    let part_duration_ms = total_duration_ms / t_parts as f64;
+    let mut distances = vec![0.0; part_size];
+
+    // Le déplacement est attribué à la partie de l'objet d'arrivée
+    for pair in map.hit_objects.windows(2) {
+        let index = part_index(pair[1].start_time, first_time, part_duration_ms, part_size);
+        distances[index] += hit_object_pos(&pair[0]).distance(hit_object_pos(&pair[1])) as f64;
+    }
+
+    let part_duration_sec = to_sec(part_duration_ms);
+    Some(distances.into_iter().map(|distance| distance / part_duration_sec).collect())
+}
+
+#[cfg(feature = "zip")]
+pub fn calc_nps_from_osz(data: &[u8], filename: &str) -> Option<f64> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(data)).ok()?;
+    let entry = archive.by_name(filename).ok()?;
+
+    // On lit au plus la limite + 1 octet pour détecter une entrée trop grande
+    let mut bytes = Vec::new();
+    entry.take(OSZ_ENTRY_MAX_BYTES + 1).read_to_end(&mut bytes).ok()?;
+    if bytes.len() as u64 > OSZ_ENTRY_MAX_BYTES {
+        return None;
+    }
+
+    calc_nps(&Beatmap::from_bytes(&bytes).ok()?)
+}
+
+pub fn summarize(map: &Beatmap, window_ms: f64, stream_min_nps: f64) -> Option<MapDensitySummary> {
+    let drain_time_ms = drain_time_ms(map)?;
+    let (peak_time_ms, peak_nps) = calc_peak_nps_at(map, window_ms)?;
+
+    // Un stream doit durer au moins une fenêtre pour être compté
+    let stream_count = find_streams(map, stream_min_nps, window_ms).len();
+
+    Some(MapDensitySummary {
+        overall_nps: calc_nps(map)?,
+        peak_nps,
+        peak_time_ms,
+        stream_count,
+        total_objects: object_count(map),
+        drain_time_ms,
+    })
+}
+
+pub fn density_consistency(map: &Beatmap, t_parts: i32) -> Option<f64> {
+    let non_zero: Vec<f64> = calc_distribution(map, t_parts)?
+        .into_iter()
+        .filter(|&nps| nps > 0.0)
+        .collect();
+
+    // Coefficient de variation : 0 pour une map parfaitement régulière
+    let stats = distribution_stats(&non_zero)?;
+    Some(stats.std_dev / stats.mean)
+}
+
+#[cfg(feature = "rayon")]
+pub fn calc_nps_batch(paths: &[PathBuf]) -> Vec<Option<f64>> {
+    paths
+        .par_iter()
+        .map(|path| Beatmap::from_path(path).ok().and_then(|map| calc_nps(&map)))
+        .collect()
+}
+
+#[cfg(test)]
+pub(crate) fn distributions_approx_eq(a: &[f64], b: &[f64], epsilon: f64) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(a, b)| (a - b).abs() <= epsilon)
+}
+
+pub fn drain_time_ms(map: &Beatmap) -> Option<f64> {
+    Some(map.hit_objects.last()?.start_time - map.hit_objects.first()?.start_time)
+}
+
+pub fn object_count(map: &Beatmap) -> usize {
+    map.hit_objects.len()
+}
+
+pub fn calc_nps_sustained(map: &Beatmap, interval_ms: f64) -> Option<Vec<f64>> {
+    if interval_ms <= 0.0 {
+        return None;
+    }
+
+    let first_time = map.hit_objects.first()?.start_time;
+    let mut bufs = CurveBuffers::default();
+
+    // (début, fin) de chaque objet, seuls les sliders et les holds ont une durée
+    let spans: Vec<(f64, f64)> = map
+        .hit_objects
+        .iter()
+        .map(|h| {
+            let duration = match &h.kind {
+                // Même formule que duration_with_bufs, sans copier le slider
+                HitObjectKind::Slider(slider) => {
+                    f64::from(slider.span_count()) * slider.path.borrowed_curve(&mut bufs).dist() / slider.velocity
+                }
+                HitObjectKind::Hold(hold) => hold.duration,
+                HitObjectKind::Circle(_) | HitObjectKind::Spinner(_) => 0.0,
+            };
+            (h.start_time, h.start_time + duration.max(0.0))
+        })
+        .collect();
+
+    let last_time = spans.iter().map(|&(_, end)| end).fold(first_time, f64::max);
+    let n_intervals = ((last_time - first_time) / interval_ms) as usize + 1;
+    let mut counts = vec![0.0; n_intervals];
+
+    for (start, end) in spans {
+        let first_idx = (((start - first_time) / interval_ms) as usize).min(n_intervals - 1);
+        if end <= start {
+            counts[first_idx] += 1.0;
+            continue;
+        }
+
+        // Chaque intervalle reçoit la fraction de la durée de l'objet qu'il recouvre
+        let last_idx = (((end - first_time) / interval_ms) as usize).min(n_intervals - 1);
+        for (i, count) in counts.iter_mut().enumerate().take(last_idx + 1).skip(first_idx) {
+            let interval_start = first_time + i as f64 * interval_ms;
+            let overlap = end.min(interval_start + interval_ms) - start.max(interval_start);
+            *count += overlap.max(0.0) / (end - start);
+        }
+    }
+
+    let interval_sec = to_sec(interval_ms);
+    Some(counts.into_iter().map(|count| count / interval_sec).collect())
+}
+
+pub fn resample_distribution(values: &[f64], new_len: usize) -> Vec<f64> {
+    if values.is_empty() || new_len == 0 {
+        return vec![0.0; new_len];
+    }
+    if values.len() == 1 || new_len == 1 {
+        return vec![values[0]; new_len];
+    }
+
+    // Les extrémités sont conservées, les points intermédiaires interpolés linéairement
+    let scale = (values.len() - 1) as f64 / (new_len - 1) as f64;
+    (0..new_len)
+        .map(|i| {
+            let x = i as f64 * scale;
+            let lower = (x.floor() as usize).min(values.len() - 1);
+            let upper = (lower + 1).min(values.len() - 1);
+            values[lower] + (values[upper] - values[lower]) * (x - lower as f64)
+        })
+        .collect()
+}
+
+pub fn note_gaps(map: &Beatmap) -> Vec<f64> {
+    map.hit_objects
+        .windows(2)
+        .map(|pair| pair[1].start_time - pair[0].start_time)
+        .collect()
+}
+
+pub fn trimmed_mean_nps(map: &Beatmap, t_parts: i32, trim_fraction: f64) -> Option<f64> {
+    if !(0.0..0.5).contains(&trim_fraction) {
+        return None;
+    }
+
+    let mut distribution = calc_distribution(map, t_parts)?;
+    distribution.sort_by(f64::total_cmp);
+
+    // trim_fraction < 0.5 garantit qu'il reste au moins une partie
+    let trimmed = (distribution.len() as f64 * trim_fraction) as usize;
+    let kept = &distribution[trimmed..distribution.len() - trimmed];
+
+    Some(kept.iter().sum::<f64>() / kept.len() as f64)
+}
+
+pub fn merge_spans(spans: &[(f64, f64)], max_gap_ms: f64) -> Vec<(f64, f64)> {
+    let mut merged: Vec<(f64, f64)> = Vec::with_capacity(spans.len());
+
+    for &(start, end) in spans {
+        match merged.last_mut() {
+            // Chevauchement ou écart inférieur à max_gap_ms : on prolonge le span courant
+            Some(last) if start - last.1 < max_gap_ms => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+
+    merged
+}
+
+// Estimation grossière basée sur la densité seule, pas un star rating :
+// (0.4 * nps moyenne + 0.6 * pic sur 1s) * (1 + 0.5 * part des notes dans des streams
+// d'au moins 8 NPS tenus 1s ou plus)
+pub fn density_difficulty(map: &Beatmap) -> Option<f64> {
+    let overall_nps = calc_nps(map)?;
+    let peak_nps = calc_peak_nps(map, DIFFICULTY_WINDOW_MS)?;
+
+    let stream_notes: usize = dense_runs(map, DIFFICULTY_STREAM_MIN_NPS)
+        .into_iter()
+        .filter(|&(start_idx, end_idx)| {
+            map.hit_objects[end_idx].start_time - map.hit_objects[start_idx].start_time >= DIFFICULTY_WINDOW_MS
+        })
+        .map(|(start_idx, end_idx)| end_idx - start_idx + 1)
+        .sum();
+    let stream_ratio = stream_notes as f64 / map.hit_objects.len() as f64;
+
+    Some((0.4 * overall_nps + 0.6 * peak_nps) * (1.0 + 0.5 * stream_ratio))
+}
+
+pub fn parse_hit_objects_reader<R: BufRead>(reader: R) -> Result<Vec<f64>, ParseError> {
+    // Découpage sur les octets comme le décodeur de rosu-map, l'UTF-8 invalide est remplacé
+    let lines = reader.split(b'\n').map(|line| {
+        line.map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+            .map_err(|err| ParseError::ReadFailed(err.kind()))
+    });
+    decode_hit_object_times(lines, |_, _, _| {})
+}
+
+// Reprend la boucle de HitObjects::decode : BOM retiré, lignes vides et commentaires
+// sautés, sections reconnues par Section::try_from_line. Les lignes rejetées par
+// rosu-map sont passées à `on_malformed` avec leur numéro puis écartées. Les temps
+// sortent triés par HitObjects::from, comme pour un Beatmap.
+fn decode_hit_object_times<I, S, F>(lines: I, mut on_malformed: F) -> Result<Vec<f64>, ParseError>
+where
+    I: IntoIterator<Item = Result<S, ParseError>>,
+    S: AsRef<str>,
+    F: FnMut(usize, &str, &ParseHitObjectsError),
+{
+    // La version ne change pas les temps des objets
+    let mut state = HitObjectsState::create(LATEST_FORMAT_VERSION);
+    let mut section = None;
+    let mut found_section = false;
+
+    for (i, line) in lines.into_iter().enumerate() {
+        let line = line?;
+        let line = line.as_ref();
+        let line = if i == 0 { line.trim_start_matches('\u{feff}') } else { line }.trim_end();
+
+        if HitObjects::should_skip_line(line) {
+            continue;
+        }
+        if let Some(next) = Section::try_from_line(line) {
+            found_section |= next == Section::HitObjects;
+            section = Some(next);
+            continue;
+        }
+
+        // Les autres sections ne servent qu'au contexte des sliders, leurs erreurs
+        // sont ignorées comme dans rosu-map
+        let _ = match section {
+            Some(Section::General) => HitObjects::parse_general(&mut state, line),
+            Some(Section::Difficulty) => HitObjects::parse_difficulty(&mut state, line),
+            Some(Section::Events) => HitObjects::parse_events(&mut state, line),
+            Some(Section::TimingPoints) => HitObjects::parse_timing_points(&mut state, line),
+            Some(Section::HitObjects) => {
+                if let Err(err) = HitObjects::parse_hit_objects(&mut state, line) {
+                    on_malformed(i + 1, line, &err);
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        };
+    }
+
+    if !found_section {
+        return Err(ParseError::MissingHitObjects);
+    }
+
+    let times: Vec<f64> = HitObjects::from(state).hit_objects.iter().map(|h| h.start_time).collect();
+    if times.is_empty() {
+        return Err(ParseError::EmptyHitObjects);
+    }
+    Ok(times)
+}
+
+pub fn calc_nps_with_ticks(map: &Beatmap, tick_rate: f64) -> Option<f64> {
+    if tick_rate <= 0.0 {
+        return None;
+    }
+
+    let drain_time_ms = drain_time_ms(map)?;
+    let mut bufs = CurveBuffers::default();
+
+    let total_hits: usize = map
+        .hit_objects
+        .iter()
+        .map(|h| match &h.kind {
+            HitObjectKind::Slider(slider) => 1 + slider_extra_hits(map, h.start_time, slider, tick_rate, &mut bufs),
+            _ => 1,
+        })
+        .sum();
+
+    if drain_time_ms <= 0.0 {
+        return Some(total_hits as f64);
+    }
+    Some(total_hits as f64 / to_sec(drain_time_ms))
+}
+
+// Repeats + ticks d'un slider, hors tête. Comme dans osu!, la distance entre ticks
+// vaut velocity * beat_len / tick_rate et aucun tick n'est placé à moins de
+// SLIDER_TICK_MIN_END_MS de la fin d'un span.
+fn slider_extra_hits(
+    map: &Beatmap,
+    start_time: f64,
+    slider: &HitObjectSlider,
+    tick_rate: f64,
+    bufs: &mut CurveBuffers,
+) -> usize {
+    let span_count = slider.span_count().max(1) as usize;
+    let beat_len = map
+        .control_points
+        .timing_point_at(start_time)
+        .map_or(TimingPoint::DEFAULT_BEAT_LEN, |point| point.beat_len);
+
+    // Courbe empruntée : pas de copie du slider ni du chemin
+    let span_len = slider.path.borrowed_curve(bufs).dist();
+    let tick_distance = slider.velocity * beat_len / tick_rate;
+    let min_end_distance = slider.velocity * SLIDER_TICK_MIN_END_MS;
+
+    let ticks_per_span = if tick_distance.is_finite() && tick_distance > 0.0 {
+        (((span_len - min_end_distance) / tick_distance).ceil() - 1.0).max(0.0) as usize
+    } else {
+        0
+    };
+
+    (span_count - 1) + span_count * ticks_per_span
+}
+
+// Fenêtres consécutives depuis la première note. Seules celles qui finissent avant la
+// dernière note sont candidates, sinon la queue de la map gagnerait toujours ; une map
+// plus courte que window_ms renvoie sa première fenêtre. Avec skip_empty, les fenêtres
+// sans note (pauses) sont écartées au lieu de compter pour 0.
+pub fn calc_min_nps_window(map: &Beatmap, window_ms: f64, skip_empty: bool) -> Option<(f64, f64)> {
+    if !is_window(window_ms) || map.hit_objects.is_empty() {
+        return None;
+    }
+
+    let last_time = map.hit_objects.last()?.start_time;
+    let windows = || windows_by_time(&map.hit_objects, window_ms);
+    let (start_time, count) = first_best_window(
+        windows()
+            .take_while(|&(start_time, _)| start_time + window_ms <= last_time)
+            .filter(|&(_, count)| !skip_empty || count > 0),
+        |a, b| a < b,
+    )
+    .or_else(|| windows().next())?;
+
+    Some((start_time, count as f64 / to_sec(window_ms)))
+}
+
+pub fn calc_nps_at_boundaries(map: &Beatmap, boundaries: &[f64]) -> Option<Vec<f64>> {
+    if boundaries.len() < 2 || !boundaries.windows(2).all(|pair| pair[0] <= pair[1]) {
+        return None;
+    }
+
+    // Intervalles [boundaries[i], boundaries[i + 1]) comme calc_nps_range_by_time
+    boundaries
+        .windows(2)
+        .map(|pair| calc_nps_range_by_time(map, pair[0], pair[1]))
+        .collect()
+}
+
+pub fn parse_hit_objects_lenient(content: &str) -> (Vec<f64>, Vec<String>) {
+    let mut warnings = Vec::new();
+    let result = decode_hit_object_times(content.lines().map(Ok), |line_number, line, err| {
+        warnings.push(format!("line {line_number}: malformed hit object `{line}`: {err}"));
+    });
+
+    match result {
+        Ok(times) => (times, warnings),
+        // Une section vide n'est pas une erreur de format
+        Err(ParseError::EmptyHitObjects) => (Vec::new(), warnings),
+        Err(err) => {
+            warnings.push(err.to_string());
+            (Vec::new(), warnings)
+        }
+    }
+}
+
+pub fn calc_nps_per_timing_section(map: &Beatmap) -> Option<Vec<(f64, f64, f64)>> {
+    let first_time = map.hit_objects.first()?.start_time;
+    let last_time = map.hit_objects.last()?.start_time;
+    let timing_points = &map.control_points.timing_points;
+
+    if timing_points.len() < 2 {
+        return Some(vec![(first_time, last_time, calc_nps(map)?)]);
+    }
+
+    let n_sections = timing_points.len();
+    let sections = (0..n_sections)
+        .map(|i| {
+            // La première section couvre les notes avant le premier point,
+            // la dernière va jusqu'à la dernière note incluse
+            let start = if i == 0 { timing_points[0].time.min(first_time) } else { timing_points[i].time };
+            let is_last = i == n_sections - 1;
+            let end = if is_last { last_time.max(start) } else { timing_points[i + 1].time };
+
+            let start_idx = map.hit_objects.partition_point(|h| h.start_time < start);
+            let end_idx = if is_last {
+                map.hit_objects.len()
+            } else {
+                map.hit_objects.partition_point(|h| h.start_time < end)
+            };
+
+            let duration_ms = end - start;
+            let count = end_idx.saturating_sub(start_idx);
+            let nps = if duration_ms > 0.0 { count as f64 / to_sec(duration_ms) } else { 0.0 };
+
+            (start, end, nps)
+        })
+        .collect();
+
+    Some(sections)
+}
+
+pub fn calc_nps_per_measure(map: &Beatmap) -> Option<Vec<f64>> {
+    let first_time = map.hit_objects.first()?.start_time;
+    let last_time = map.hit_objects.last()?.start_time;
+    let timing_points = &map.control_points.timing_points;
+    if timing_points.is_empty() {
+        return None;
+    }
+
+    // Bornes (début, durée) de chaque mesure, section par section
+    let mut measures: Vec<(f64, f64)> = Vec::new();
+    for (i, point) in timing_points.iter().enumerate() {
+        let measure_len = point.beat_len * f64::from(point.time_signature.numerator.get());
+        if !measure_len.is_finite() || measure_len <= 0.0 {
+            return None;
+        }
+
+        let mut start = point.time;
+        if i == 0 {
+            // Recule la grille jusqu'à couvrir les notes avant le premier point
+            while start > first_time {
+                start -= measure_len;
+            }
+        }
+
+        let section_end = timing_points.get(i + 1).map_or(f64::INFINITY, |next| next.time);
+        while start < section_end && start <= last_time {
+            measures.push((start, measure_len.min(section_end - start)));
+            start += measure_len;
+        }
+    }
+
+    let nps = measures
+        .iter()
+        .map(|&(start, len)| {
+            let start_idx = map.hit_objects.partition_point(|h| h.start_time < start);
+            let end_idx = map.hit_objects.partition_point(|h| h.start_time < start + len);
+            end_idx.saturating_sub(start_idx) as f64 / to_sec(len)
+        })
+        .collect();
+
+    Some(nps)
+}
+
+// Jusqu'à la dernière note incluse, contrairement à calc_nps_range_by_time
+pub fn calc_nps_from(map: &Beatmap, start_time: f64) -> Option<f64> {
+    let last_time = map.hit_objects.last()?.start_time;
+    let drain_time_ms = last_time - start_time;
+    if drain_time_ms <= 0.0 {
+        return Some(0.0);
+    }
+
+    let start_idx = map.hit_objects.partition_point(|h| h.start_time < start_time);
+    let count = map.hit_objects.len() - start_idx;
+    Some(count as f64 / to_sec(drain_time_ms))
+}
+
+// [première note, end_time), comme calc_nps_range_by_time
+pub fn calc_nps_until(map: &Beatmap, end_time: f64) -> Option<f64> {
+    let first_time = map.hit_objects.first()?.start_time;
+    calc_nps_range_by_time(map, first_time, end_time)
+}
+
+pub fn nth_note_time(map: &Beatmap, n: usize) -> Option<f64> {
+    map.hit_objects.get(n).map(|h| h.start_time)
+}
+
+pub fn split_series(series: &[KeyValue]) -> (Vec<i32>, Vec<f64>) {
+    series.iter().map(|kv| (kv.key, kv.value)).unzip()
+}
+
+// Contrairement à calc_peak_nps_at la fenêtre reste dans la map : la dernière candidate
+// est [last - duration_ms, last]. Une map plus courte que duration_ms renvoie sa première
+// note et toutes ses notes réparties sur duration_ms.
+pub fn hardest_window(map: &Beatmap, duration_ms: f64) -> Option<(f64, f64)> {
+    if !is_window(duration_ms) {
+        return None;
+    }
+
+    let objects = &map.hit_objects;
+    let first_time = objects.first()?.start_time;
+    let last_time = objects.last()?.start_time;
+    let duration_sec = to_sec(duration_ms);
+
+    if last_time - first_time <= duration_ms {
+        return Some((first_time, objects.len() as f64 / duration_sec));
+    }
+
+    // Dernière fenêtre calée sur la dernière note, incluse
+    let tail_start = last_time - duration_ms;
+    let tail_count = objects.len() - objects.partition_point(|h| h.start_time < tail_start);
+
+    let candidates = anchored_windows(objects, duration_ms)
+        .take_while(|&(start, _)| start + duration_ms <= last_time)
+        .chain(std::iter::once((tail_start, tail_count)));
+    let (start, count) = first_best_window(candidates, |a, b| a > b)?;
+
+    Some((start, count as f64 / duration_sec))
+}
+
+// NPS de l'objet et de jusqu'à `neighbors` voisins de chaque côté, calculée comme
+// calc_nps sur cette tranche
+pub fn instantaneous_nps(map: &Beatmap, neighbors: usize) -> Vec<f64> {
+    let objects = &map.hit_objects;
+    let last_idx = objects.len().saturating_sub(1);
+
+    (0..objects.len())
+        .map(|i| {
+            let lo = i.saturating_sub(neighbors);
+            let hi = i.saturating_add(neighbors).min(last_idx);
+            let count = (hi - lo + 1) as f64;
+            let span_ms = objects[hi].start_time - objects[lo].start_time;
+
+            if span_ms > 0.0 { count / to_sec(span_ms) } else { count }
+        })
+        .collect()
+}
+
+pub fn calc_nps_excluding_spinners(map: &Beatmap) -> Option<f64> {
+    let first_time = map.hit_objects.first()?.start_time;
+    let last_time = map.hit_objects.last()?.start_time;
+
+    let mut count = 0;
+    let mut spinner_time_ms = 0.0;
+    for h in &map.hit_objects {
+        match &h.kind {
+            HitObjectKind::Spinner(spinner) => {
+                spinner_time_ms += clipped_duration_ms(h.start_time, h.start_time + spinner.duration, first_time, last_time);
+            }
+            _ => count += 1,
+        }
+    }
+
+    let drain_time_ms = last_time - first_time - spinner_time_ms;
+    if drain_time_ms <= 0.0 {
+        return Some(count as f64);
+    }
+    Some(count as f64 / to_sec(drain_time_ms))
+}
+
+// Fenêtre [time_ms - window_ms / 2, time_ms + window_ms / 2)
+pub fn nps_at_time(map: &Beatmap, time_ms: f64, window_ms: f64) -> Option<f64> {
+    if !is_window(window_ms) || map.hit_objects.is_empty() {
+        return None;
+    }
+
+    let half_window = window_ms / 2.0;
+    calc_nps_range_by_time(map, time_ms - half_window, time_ms + half_window)
+}
+
+pub fn decimate_peaks(values: &[f64], target_len: usize) -> Vec<f64> {
+    if values.len() <= target_len {
+        return values.to_vec();
+    }
+
+    // Chaque case garde son maximum pour ne pas lisser les pics
+    (0..target_len)
+        .map(|i| {
+            let start = i * values.len() / target_len;
+            let end = (i + 1) * values.len() / target_len;
+            values[start..end].iter().copied().fold(f64::NEG_INFINITY, f64::max)
+        })
+        .collect()
+}
+
+// Variation de NPS par seconde entre parties consécutives, 0 pour la première
+pub fn density_gradient(values: &[f64], part_duration_ms: f64) -> Vec<f64> {
+    if values.is_empty() || part_duration_ms.is_nan() || part_duration_ms <= 0.0 {
+        return vec![0.0; values.len()];
+    }
+
+    let part_duration_sec = to_sec(part_duration_ms);
+    std::iter::once(0.0)
+        .chain(values.windows(2).map(|pair| (pair[1] - pair[0]) / part_duration_sec))
+        .collect()
+}
+
+// Un juice stream compte tête, repeats et queue comme fruits plus un droplet par tick
+// (les tiny droplets sont ignorés), un banana shower compte ses bananes
+pub fn calc_catch_density(map: &Beatmap) -> Option<f64> {
+    if map.mode != GameMode::Catch {
+        return None;
+    }
+
+    let drain_time_ms = drain_time_ms(map)?;
+    let mut bufs = CurveBuffers::default();
+
+    let total_items: usize = map
+        .hit_objects
+        .iter()
+        .map(|h| match &h.kind {
+            HitObjectKind::Slider(slider) => {
+                2 + slider_extra_hits(map, h.start_time, slider, map.slider_tick_rate, &mut bufs)
+            }
+            HitObjectKind::Spinner(spinner) => banana_count(spinner.duration),
+            _ => 1,
+        })
+        .sum();
+
+    if drain_time_ms <= 0.0 {
+        return Some(total_items as f64);
+    }
+    Some(total_items as f64 / to_sec(drain_time_ms))
+}
+
+fn banana_count(duration_ms: f64) -> usize {
+    if !duration_ms.is_finite() || duration_ms <= 0.0 {
+        return 0;
+    }
+
+    // La durée est divisée par deux jusqu'à passer sous BANANA_MAX_SPACING_MS
+    let mut spacing = duration_ms;
+    while spacing > BANANA_MAX_SPACING_MS {
+        spacing /= 2.0;
+    }
+
+    // Une banane au début puis une tous les `spacing` jusqu'à la fin incluse
+    (duration_ms / spacing).floor() as usize + 1
+}
+
+// Les fonctions basées sur partition_point supposent des temps triés : on supprime les
+// temps non finis, on ramène les négatifs à 0 et on retrie. Renvoie le nombre supprimé.
+pub fn sanitize_timings(map: &mut Beatmap) -> usize {
+    let len_before = map.hit_objects.len();
+    map.hit_objects.retain(|h| h.start_time.is_finite());
+
+    for h in &mut map.hit_objects {
+        h.start_time = h.start_time.max(0.0);
+    }
+    map.hit_objects.sort_by(|a, b| a.start_time.total_cmp(&b.start_time));
+
+    len_before - map.hit_objects.len()
+}
+
+// [start_pct, end_pct) en pourcentage (0–100) de la durée entre première et dernière
+// note, end_pct = 100 inclut la dernière note. Durée nulle : toute plage couvre l'unique
+// instant, on renvoie donc calc_nps (le nombre d'objets)
+pub fn calc_nps_percent_range(map: &Beatmap, start_pct: f64, end_pct: f64) -> Option<f64> {
+    if !(0.0 <= start_pct && start_pct < end_pct && end_pct <= 100.0) {
+        return None;
+    }
+
+    let first_time = map.hit_objects.first()?.start_time;
+    let drain_time_ms = drain_time_ms(map)?;
+    if drain_time_ms <= 0.0 {
+        return calc_nps(map);
+    }
+    let start_time = first_time + drain_time_ms * start_pct / 100.0;
+
+    if end_pct == 100.0 {
+        return calc_nps_from(map, start_time);
+    }
+    calc_nps_range_by_time(map, start_time, first_time + drain_time_ms * end_pct / 100.0)
+}
+
+pub fn analyze(map: &Beatmap, t_parts: i32, window_ms: f64) -> Option<MapAnalysis> {
+    let distribution = calc_distribution(map, t_parts)?;
+    let stats = distribution_stats(&distribution)?;
+
+    Some(MapAnalysis {
+        overall_nps: calc_nps(map)?,
+        peak_nps: calc_peak_nps(map, window_ms)?,
+        distribution,
+        stats,
+    })
+}
+
+pub fn longest_gap(map: &Beatmap) -> Option<(f64, f64)> {
+    // À écart égal, le premier l'emporte
+    map.hit_objects
+        .windows(2)
+        .map(|pair| (pair[0].start_time, pair[1].start_time))
+        .fold(None, |best: Option<(f64, f64)>, curr| match best {
+            Some(best) if best.1 - best.0 >= curr.1 - curr.0 => Some(best),
+            _ => Some(curr),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load_test_map() -> Beatmap {
+        Beatmap::from_bytes(include_bytes!("../assets/8thera.osu")).unwrap()
+    }
+
+    // Map osu! minimale avec un cercle par temps donné
+    fn map_with_times(times: &[f64]) -> Beatmap {
+        let mut content = String::from("osu file format v14\n\n[HitObjects]\n");
+        for time in times {
+            content.push_str(&format!("256,192,{time},1,0,0:0:0:0:\n"));
+        }
+        Beatmap::from_bytes(content.as_bytes()).unwrap()
+    }
+
+    // Map osu! minimale à partir de ses sections
+    fn map_from_sections(sections: &str) -> Beatmap {
+        Beatmap::from_bytes(format!("osu file format v14\n\n{sections}").as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn adjacent_time_ranges_count_every_note_once() {
+        let map = load_test_map();
+        let first_time = map.hit_objects.first().unwrap().start_time;
+        // Borne de fin exclusive : on dépasse la dernière note pour l'inclure
+        let end_time = map.hit_objects.last().unwrap().start_time + 1.0;
+
+        for parts in [1, 7, 64, 1000] {
+            let step = (end_time - first_time) / parts as f64;
+            let total: f64 = (0..parts)
+                .map(|i| {
+                    let start = first_time + i as f64 * step;
+                    let end = if i == parts - 1 { end_time } else { first_time + (i + 1) as f64 * step };
+                    calc_nps_range_by_time(&map, start, end).unwrap() * to_sec(end - start)
+                })
+                .sum();
+
+            assert_eq!(total.round() as usize, map.hit_objects.len(), "parts = {parts}");
+        }
+    }
+
+    #[test]
+    fn distribution_variants_agree() {
+        let map = load_test_map();
+
+        // 21, 42 et 52 tombaient sur des bornes de partie arrondies différemment
+        for parts in [1, 2, 3, 5, 7, 10, 20, 21, 42, 50, 52, 64, 100, 200, 500, 1000] {
+            let binary_search = calc_distribution(&map, parts).unwrap();
+            let bucketing = calc_distribution_2(&map, parts).unwrap();
+            let smart = calc_distribution_smart(&map, parts).unwrap();
+
+            assert!(distributions_approx_eq(&binary_search, &bucketing, 1e-9), "parts = {parts}");
+            assert!(distributions_approx_eq(&binary_search, &smart, 1e-9), "parts = {parts}");
+        }
+    }
+
+    #[test]
+    fn single_timestamp_map_returns_object_count() {
+        let chord = b"osu file format v14
+
+[General]
+Mode: 3
+
+[Difficulty]
+CircleSize:4
+
+[HitObjects]
+64,192,1000,1,0,0:0:0:0:
+192,192,1000,1,0,0:0:0:0:
+320,192,1000,1,0,0:0:0:0:
+448,192,1000,1,0,0:0:0:0:
+";
+        let map = Beatmap::from_bytes(chord).unwrap();
+
+        assert_eq!(calc_nps(&map), Some(4.0));
+        assert_eq!(calc_distribution(&map, 4), Some(vec![0.0; 4]));
+        assert_eq!(calc_distribution_2(&map, 4), Some(vec![0.0; 4]));
+    }
+
+    #[test]
+    fn count_distribution_matches_nps_distribution() {
+        let map = load_test_map();
+        let total_duration_ms = drain_time_ms(&map).unwrap();
+
+        for parts in [1, 3, 21, 64, 1000] {
+            let counts = calc_count_distribution(&map, parts).unwrap();
+            assert_eq!(counts.iter().sum::<usize>(), map.hit_objects.len(), "parts = {parts}");
+
+            let part_duration_sec = to_sec(total_duration_ms / parts as f64);
+            let as_nps: Vec<f64> = counts.iter().map(|&count| count as f64 / part_duration_sec).collect();
+            assert!(distributions_approx_eq(&as_nps, &calc_distribution(&map, parts).unwrap(), 1e-9), "parts = {parts}");
+        }
+    }
+
+    #[test]
+    fn window_primitives_pin_boundaries() {
+        // Une note pile sur start + window_ms appartient à la fenêtre suivante
+        let times = [0.0, 100.0, 100.0, 250.0, 300.0];
+        assert_eq!(
+            windows_by_time(&times, 100.0).collect::<Vec<_>>(),
+            vec![(0.0, 1), (100.0, 2), (200.0, 1), (300.0, 1)]
+        );
+        // La dernière fenêtre glissante reste ouverte à droite
+        assert_eq!(
+            sliding_windows_by_time(&times, 200.0, 100.0).collect::<Vec<_>>(),
+            vec![(0.0, 3), (100.0, 3), (200.0, 2), (300.0, 1)]
+        );
+        // Une seule fenêtre ancrée par instant
+        assert_eq!(
+            anchored_windows(&times, 100.0).collect::<Vec<_>>(),
+            vec![(0.0, 1), (100.0, 2), (250.0, 2), (300.0, 1)]
+        );
+
+        // Notes sur les bornes des parties : chacune ouvre sa partie, la dernière partie
+        // garde la dernière note
+        let map = map_with_times(&[0.0, 100.0, 200.0, 300.0, 400.0]);
+        assert_eq!(calc_count_distribution(&map, 4), Some(vec![1, 1, 1, 2]));
+        assert_eq!(calc_distribution(&map, 4), Some(vec![10.0, 10.0, 10.0, 20.0]));
+        assert_eq!(calc_distribution_2(&map, 4), calc_distribution(&map, 4));
+        assert_eq!(time_windows(&map, 100.0), vec![(0.0, 1), (100.0, 1), (200.0, 1), (300.0, 1), (400.0, 1)]);
+    }
+
+    #[cfg(feature = "zip")]
+    fn osz_with_entry(filename: &str, content: &[u8]) -> Vec<u8> {
+        use std::io::Write;
+        use zip::write::SimpleFileOptions;
+
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        writer.start_file(filename, options).unwrap();
+        writer.write_all(content).unwrap();
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[cfg(feature = "zip")]
+    #[test]
+    fn osz_entry_is_read_and_bounded() {
+        let content = include_bytes!("../assets/8thera.osu");
+        let osz = osz_with_entry("8thera.osu", content);
+        assert_eq!(calc_nps_from_osz(&osz, "8thera.osu"), calc_nps(&load_test_map()));
+        assert_eq!(calc_nps_from_osz(&osz, "absent.osu"), None);
+        assert_eq!(calc_nps_from_osz(b"not a zip", "8thera.osu"), None);
+
+        // Une entrée au-delà de la limite est refusée sans être chargée en entier
+        let oversized = vec![b' '; OSZ_ENTRY_MAX_BYTES as usize + 1];
+        let osz = osz_with_entry("big.osu", &oversized);
+        assert_eq!(calc_nps_from_osz(&osz, "big.osu"), None);
+    }
+
+    // Objets désordonnés, une ligne rejetée par rosu-map (type manquant) et un faux en-tête
+    const UNSORTED_HIT_OBJECTS: &str = "\u{feff}osu file format v14\r\n\r\n[HitObjects]\r\n\
+        256,192,300,1,0,0:0:0:0:\r\n\
+        256,192,100,1,0,0:0:0:0:\r\n\
+        256,192,200\r\n\
+        [NotASection]\r\n\
+        // commentaire\r\n\
+        256,192,200,1,0,0:0:0:0:\r\n";
+
+    #[test]
+    fn reader_matches_beatmap_decoding() {
+        let bytes = include_bytes!("../assets/8thera.osu");
+        let expected: Vec<f64> = load_test_map().hit_objects.iter().map(|h| h.start_time).collect();
+        assert_eq!(parse_hit_objects_reader(&bytes[..]), Ok(expected));
+
+        let map = Beatmap::from_bytes(UNSORTED_HIT_OBJECTS.as_bytes()).unwrap();
+        let expected: Vec<f64> = map.hit_objects.iter().map(|h| h.start_time).collect();
+        assert_eq!(expected, vec![100.0, 200.0, 300.0]);
+        assert_eq!(parse_hit_objects_reader(UNSORTED_HIT_OBJECTS.as_bytes()), Ok(expected));
+    }
+
+    #[test]
+    fn reader_distinguishes_missing_and_empty_sections() {
+        let missing = "osu file format v14\n\n[General]\nMode: 0\n";
+        assert_eq!(parse_hit_objects_reader(missing.as_bytes()), Err(ParseError::MissingHitObjects));
+
+        let empty = "osu file format v14\n\n[HitObjects]\n\n";
+        assert_eq!(parse_hit_objects_reader(empty.as_bytes()), Err(ParseError::EmptyHitObjects));
+
+        let invalid_only = "osu file format v14\n\n[HitObjects]\n256,192\n";
+        assert_eq!(parse_hit_objects_reader(invalid_only.as_bytes()), Err(ParseError::EmptyHitObjects));
+    }
+
+    #[test]
+    fn lenient_parser_warns_and_matches_reader() {
+        let (times, warnings) = parse_hit_objects_lenient(UNSORTED_HIT_OBJECTS);
+        assert_eq!(Ok(times), parse_hit_objects_reader(UNSORTED_HIT_OBJECTS.as_bytes()));
+        // Le BOM est retiré : la ligne de version n'est pas vue comme un objet
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].starts_with("line 6: malformed hit object `256,192,200`"));
+        assert!(warnings[1].starts_with("line 7: malformed hit object `[NotASection]`"));
+
+        let content = std::str::from_utf8(include_bytes!("../assets/8thera.osu")).unwrap();
+        let (times, warnings) = parse_hit_objects_lenient(content);
+        assert!(warnings.is_empty());
+        assert_eq!(Ok(times), parse_hit_objects_reader(content.as_bytes()));
+
+        let (times, warnings) = parse_hit_objects_lenient("osu file format v14\n\n[General]\nMode: 0\n");
+        assert!(times.is_empty());
+        assert_eq!(warnings, vec![ParseError::MissingHitObjects.to_string()]);
+    }
+
+    #[test]
+    fn hit_object_range_requires_objects_of_the_map() {
+        let map = map_with_times(&[0.0, 500.0, 500.0, 1000.0]);
+        let objects = &map.hit_objects;
+        assert_eq!(calc_nps_range_by_hitobjects(&map, &objects[0], &objects[3]), Some(4.0));
+        // L'ordre des bornes n'a pas d'importance
+        assert_eq!(calc_nps_range_by_hitobjects(&map, &objects[3], &objects[0]), Some(4.0));
+        // Même instant : durée nulle
+        assert_eq!(calc_nps_range_by_hitobjects(&map, &objects[1], &objects[2]), Some(0.0));
+        assert_eq!(calc_nps_range_by_hitobjects(&map, &objects[1], &objects[1]), Some(0.0));
+
+        // Objet identique en valeur mais hors de la map
+        let other = map_with_times(&[0.0, 500.0, 500.0, 1000.0]);
+        assert_eq!(calc_nps_range_by_hitobjects(&map, &objects[0], &other.hit_objects[3]), None);
+        assert_eq!(calc_nps_range_by_hitobjects(&map, &other.hit_objects[1], &other.hit_objects[1]), None);
+    }
+
+    #[test]
+    fn aggregate_peak_validates_decay() {
+        let map = load_test_map();
+        // Poids du pic : decay^0 vaut 1 même pour decay = 0
+        assert_eq!(0f64.powi(0), 1.0);
+        assert_eq!(aggregate_peak_nps(&map, 1000.0, 0.0), calc_peak_nps(&map, 1000.0));
+
+        let peaks_sum: f64 = anchored_windows(&map.hit_objects, 1000.0).map(|(_, count)| count as f64).sum();
+        assert!((aggregate_peak_nps(&map, 1000.0, 1.0).unwrap() - peaks_sum).abs() < 1e-6);
+
+        for decay in [-0.1, 1.5, f64::NAN, f64::INFINITY] {
+            assert_eq!(aggregate_peak_nps(&map, 1000.0, decay), None);
+        }
+        assert_eq!(aggregate_peak_nps(&map, f64::NAN, 0.5), None);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn parallel_distribution_matches_serial() {
+        // Notes pile sur les bornes des parties et doublons sur une borne
+        let map = map_with_times(&[0.0, 100.0, 200.0, 200.0, 300.0, 400.0]);
+        for parts in 1..=8 {
+            assert_eq!(calc_distribution_parallel(&map, parts), calc_distribution(&map, parts));
+        }
+
+        let map = load_test_map();
+        for parts in [1, 3, 21, 100, 1000] {
+            assert_eq!(calc_distribution_parallel(&map, parts), calc_distribution(&map, parts));
+        }
+    }
+
+    #[test]
+    fn min_window_handles_gaps_ties_and_short_maps() {
+        // Map plus courte qu'une fenêtre : première fenêtre, toutes les notes
+        let map = map_with_times(&[0.0, 100.0, 200.0]);
+        assert_eq!(calc_min_nps_window(&map, 1000.0, false), Some((0.0, 3.0)));
+        assert_eq!(calc_min_nps_window(&map, 1000.0, true), Some((0.0, 3.0)));
+
+        // Égalité : la fenêtre la plus tôt l'emporte
+        let map = map_with_times(&[0.0, 1000.0, 2000.0, 3000.0, 4000.0]);
+        assert_eq!(calc_min_nps_window(&map, 1000.0, false), Some((0.0, 1.0)));
+
+        // Pause de deux fenêtres : 0 en mode comptage, ignorée avec skip_empty
+        let map = map_with_times(&[0.0, 500.0, 3000.0, 3500.0, 4000.0, 4500.0, 5000.0]);
+        assert_eq!(calc_min_nps_window(&map, 1000.0, false), Some((1000.0, 0.0)));
+        assert_eq!(calc_min_nps_window(&map, 1000.0, true), Some((0.0, 2.0)));
+        assert_eq!(calc_min_nps_window(&map, f64::NAN, false), None);
+    }
+
+    #[test]
+    fn csv_export_uses_series_keys() {
+        use crate::export::distribution_to_csv;
+
+        let map = load_test_map();
+        let first_time = map.hit_objects[0].start_time;
+        let series = calc_nps_series(&map, 1000.0).unwrap();
+        let keys: Vec<i32> = series.iter().map(|kv| kv.key).collect();
+        let values: Vec<f64> = series.iter().map(|kv| kv.value).collect();
+
+        let csv = distribution_to_csv(&values, first_time, 1000.0);
+        let csv_keys: Vec<i32> = csv
+            .lines()
+            .skip(1)
+            .map(|line| line.split(',').next().unwrap().parse::<f64>().unwrap() as i32)
+            .collect();
+        assert_eq!(csv_keys, keys);
+    }
+
+    #[test]
+    fn json_export_uses_series_keys() {
+        use crate::export::distribution_to_json;
+
+        let map = load_test_map();
+        let first_time = map.hit_objects[0].start_time;
+        let series = calc_nps_series(&map, 1000.0).unwrap();
+        let values: Vec<f64> = series.iter().map(|kv| kv.value).collect();
+
+        // Mêmes clés i32 et mêmes valeurs : le JSON est celui de la série
+        let json = distribution_to_json(&values, first_time, 1000.0).unwrap();
+        assert_eq!(json, serde_json::to_string(&series).unwrap());
+    }
+
+    // 120 BPM, SV 1 : velocity = 100 * 1.0 / 500 = 0.2 px/ms, un span de 200px dure 1000ms
+    fn map_with_slider(slides: u32) -> Beatmap {
+        let content = format!(
+            "osu file format v14\n\n[Difficulty]\nSliderMultiplier:1\nSliderTickRate:1\n\n\
+             [TimingPoints]\n0,500,4,2,0,100,1,0\n\n[HitObjects]\n\
+             0,0,1000,2,0,L|200:0,{slides},200\n"
+        );
+        Beatmap::from_bytes(content.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn slider_ticks_follow_tick_distance() {
+        // Ticks tous les 100px, aucun à moins de 2px de la fin : 1 tick par span
+        assert_eq!(calc_nps_with_ticks(&map_with_slider(1), 1.0), Some(2.0));
+        // 2 spans : 1 repeat + 2 ticks
+        assert_eq!(calc_nps_with_ticks(&map_with_slider(2), 1.0), Some(4.0));
+        // Ticks tous les 50px : 3 par span
+        assert_eq!(calc_nps_with_ticks(&map_with_slider(1), 2.0), Some(4.0));
+        assert_eq!(calc_nps_with_ticks(&map_with_slider(1), 0.0), None);
+    }
+
+    #[test]
+    fn sustained_spreads_slider_duration() {
+        // Slider de 1000 à 2000ms (2 spans : 1000 à 3000ms) réparti sur des intervalles de 500ms
+        assert_eq!(calc_nps_sustained(&map_with_slider(1), 500.0), Some(vec![1.0, 1.0, 0.0]));
+        assert_eq!(calc_nps_sustained(&map_with_slider(2), 500.0), Some(vec![0.5, 0.5, 0.5, 0.5, 0.0]));
+    }
+
+    #[test]
+    fn series_from_times_rejects_bad_input() {
+        let times = [0.0, 250.0, 1000.0];
+        assert_eq!(
+            calc_nps_series_from_times(&times, 500.0),
+            Ok(vec![KeyValue { key: 0, value: 4.0 }, KeyValue { key: 500, value: 0.0 }, KeyValue { key: 1000, value: 2.0 }])
+        );
+
+        for interval in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert_eq!(calc_nps_series_from_times(&times, interval), Err(CalcError::InvalidDuration));
+        }
+        assert_eq!(calc_nps_series_from_times(&times, 0.5), Err(CalcError::IntervalTooSmall));
+        assert_eq!(calc_nps_series_from_times(&[], 500.0), Err(CalcError::EmptyMap));
+
+        for bad in [-1.0, f64::NAN, f64::INFINITY, i32::MAX as f64 + 1.0] {
+            assert_eq!(calc_nps_series_from_times(&[0.0, bad], 500.0), Err(CalcError::TimingOutOfRange));
+        }
+        assert_eq!(calc_nps_series_from_times(&[250.0, 0.0], 500.0), Err(CalcError::UnsortedTimings));
+    }
+
+    #[test]
+    fn peak_nps_edge_cases() {
+        assert_eq!(calc_peak_nps(&map_with_times(&[500.0]), 1000.0), Some(1.0));
+        assert_eq!(calc_peak_nps(&map_with_times(&[]), 1000.0), None);
+        for window in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert_eq!(calc_peak_nps(&map_with_times(&[0.0, 100.0]), window), None);
+        }
+
+        // Fenêtre [start, start + window_ms) : une note pile sur la fin n'en fait pas partie
+        assert_eq!(calc_peak_nps(&map_with_times(&[0.0, 1000.0]), 1000.0), Some(1.0));
+        assert_eq!(calc_peak_nps(&map_with_times(&[0.0, 999.0]), 1000.0), Some(2.0));
+    }
+
+    #[test]
+    fn peak_nps_at_reports_earliest_window() {
+        assert_eq!(calc_peak_nps_at(&map_with_times(&[500.0]), 1000.0), Some((500.0, 1.0)));
+        assert_eq!(calc_peak_nps_at(&map_with_times(&[500.0]), 0.0), None);
+        assert_eq!(calc_peak_nps_at(&map_with_times(&[500.0]), f64::NAN), None);
+
+        // Deux pics égaux : le plus tôt l'emporte
+        let map = map_with_times(&[0.0, 100.0, 1000.0, 1100.0]);
+        assert_eq!(calc_peak_nps_at(&map, 500.0), Some((0.0, 4.0)));
+
+        // Les notes sur start + window_ms ouvrent la fenêtre suivante
+        let map = map_with_times(&[0.0, 1000.0, 1000.0]);
+        assert_eq!(calc_peak_nps_at(&map, 1000.0), Some((1000.0, 2.0)));
+    }
+
+    #[test]
+    fn per_column_nps_splits_mania_keys() {
+        // 4K : colonnes de 128px, x = 64 tombe en colonne 0 et x = 448 en colonne 3
+        let map = map_from_sections(
+            "[General]\nMode: 3\n\n[Difficulty]\nCircleSize:4\n\n[HitObjects]\n\
+             64,192,0,1,0,0:0:0:0:\n192,192,0,1,0,0:0:0:0:\n64,192,500,1,0,0:0:0:0:\n448,192,1000,1,0,0:0:0:0:\n",
+        );
+        assert_eq!(calc_nps_per_column(&map), Some(vec![2.0, 1.0, 0.0, 1.0]));
+
+        // Hors mania il n'y a pas de colonnes
+        assert_eq!(calc_nps_per_column(&map_with_times(&[0.0, 1000.0])), None);
+    }
+
+    #[test]
+    fn nps_series_starts_at_first_note() {
+        let map = map_with_times(&[100.0, 350.0, 1100.0]);
+        assert_eq!(
+            calc_nps_series(&map, 500.0),
+            Some(vec![KeyValue { key: 100, value: 4.0 }, KeyValue { key: 600, value: 0.0 }, KeyValue { key: 1100, value: 2.0 }])
+        );
+        assert_eq!(calc_nps_series(&map, 0.0), None);
+        assert_eq!(calc_nps_series(&map_with_times(&[]), 500.0), None);
+    }
+
+    #[test]
+    fn smoothing_uses_centered_truncated_windows() {
+        let values = [0.0, 3.0, 6.0, 9.0];
+        assert_eq!(smooth_distribution(&values, 1), values.to_vec());
+        // Aux bords la fenêtre est tronquée
+        assert_eq!(smooth_distribution(&values, 3), vec![1.5, 3.0, 6.0, 7.5]);
+        // Fenêtre paire : un voisin de plus à droite
+        assert_eq!(smooth_distribution(&values, 2), vec![1.5, 4.5, 7.5, 9.0]);
+        assert!(smooth_distribution(&[], 3).is_empty());
+    }
+
+    #[test]
+    fn rate_mods_scale_drain_time() {
+        let map = map_with_times(&[0.0, 500.0, 1000.0]);
+        assert_eq!(calc_nps_with_rate(&map, RateMod::Custom(1.0)), calc_nps(&map));
+        assert_eq!(calc_nps_with_rate(&map, RateMod::Custom(2.0)), Some(6.0));
+        assert!((calc_nps_with_rate(&map, RateMod::DoubleTime).unwrap() - 4.5).abs() < 1e-9);
+        assert!((calc_nps_with_rate(&map, RateMod::HalfTime).unwrap() - 2.25).abs() < 1e-9);
+
+        for rate in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert_eq!(calc_nps_with_rate(&map, RateMod::Custom(rate)), None);
+        }
+    }
+
+    #[test]
+    fn weighted_nps_with_unit_weights_is_plain_nps() {
+        let map = load_test_map();
+        assert_eq!(calc_nps_weighted(&map, 1.0, 1.0), calc_nps(&map));
+
+        // Un cercle puis un spinner sur une seconde
+        let map = map_from_sections("[HitObjects]\n256,192,0,1,0,0:0:0:0:\n256,192,1000,8,0,2000\n");
+        assert_eq!(calc_nps_weighted(&map, 1.0, 1.0), Some(2.0));
+        assert_eq!(calc_nps_weighted(&map, 1.0, 0.0), Some(1.0));
+        assert_eq!(calc_nps_weighted(&map_with_slider(1), 0.5, 1.0), Some(0.5));
+    }
+
+    #[test]
+    fn drain_nps_removes_breaks() {
+        let map = map_with_times(&[0.0, 1000.0, 2000.0]);
+        assert!(map.breaks.is_empty());
+        assert_eq!(calc_nps_drain(&map), calc_nps(&map));
+
+        // Pause de 500 à 1500ms : 1s de drain pour 2 notes
+        let map = map_from_sections("[Events]\n2,500,1500\n\n[HitObjects]\n256,192,0,1,0,0:0:0:0:\n256,192,2000,1,0,0:0:0:0:\n");
+        assert_eq!(calc_nps(&map), Some(1.0));
+        assert_eq!(calc_nps_drain(&map), Some(2.0));
+    }
+
+    #[test]
+    fn distribution_stats_summarize_values() {
+        let stats = distribution_stats(&[4.0, 1.0, 3.0, 2.0]).unwrap();
+        assert_eq!(stats.mean, 2.5);
+        // Nombre pair de valeurs : moyenne des deux du milieu
+        assert_eq!(stats.median, 2.5);
+        assert_eq!(stats.std_dev, 1.25f64.sqrt());
+        assert_eq!((stats.min, stats.max), (1.0, 4.0));
+
+        assert_eq!(distribution_stats(&[3.0, 1.0, 2.0]).unwrap().median, 2.0);
+        assert_eq!(distribution_stats(&[]), None);
+    }
+
+    #[test]
+    fn streams_need_density_and_duration() {
+        let map = map_with_times(&[0.0, 100.0, 200.0, 300.0, 1000.0, 1100.0, 2000.0]);
+        // 10 NPS : écarts d'au plus 100ms, bornes incluses
+        assert_eq!(find_streams(&map, 10.0, 0.0), vec![(0.0, 300.0), (1000.0, 1100.0)]);
+        assert_eq!(find_streams(&map, 10.0, 200.0), vec![(0.0, 300.0)]);
+        assert_eq!(find_streams(&map, 11.0, 0.0), vec![]);
+        assert_eq!(find_streams(&map, 0.0, 0.0), vec![]);
+    }
+
+    #[test]
+    fn nps_from_bytes_reports_empty_maps() {
+        let bytes = include_bytes!("../assets/8thera.osu");
+        assert_eq!(calc_nps_from_bytes(bytes).ok(), calc_nps(&load_test_map()));
+        assert_eq!(calc_nps_from_bytes(b"osu file format v14\n\n[HitObjects]\n"), Err(CalcError::EmptyMap));
+    }
+
+    #[test]
+    fn notes_per_beat_uses_primary_bpm() {
+        // 120 BPM et une note par demi-temps sur une seconde
+        let map = map_from_sections(
+            "[TimingPoints]\n0,500,4,2,0,100,1,0\n\n[HitObjects]\n\
+             256,192,0,1,0,0:0:0:0:\n256,192,250,1,0,0:0:0:0:\n256,192,500,1,0,0:0:0:0:\n\
+             256,192,750,1,0,0:0:0:0:\n256,192,1000,1,0,0:0:0:0:\n",
+        );
+        assert_eq!(calc_notes_per_beat(&map), Some(2.5));
+        assert_eq!(calc_notes_per_beat(&map_with_times(&[0.0, 1000.0])), None);
+    }
+
+    #[test]
+    fn interval_histogram_buckets_gaps() {
+        // Écarts 100, 50, 100 et 750ms
+        let map = map_with_times(&[0.0, 100.0, 150.0, 250.0, 1000.0]);
+        assert_eq!(interval_histogram(&map, 100.0), Some(vec![(0.0, 1), (100.0, 2), (700.0, 1)]));
+        assert_eq!(interval_histogram(&map, 0.0), None);
+        assert_eq!(interval_histogram(&map_with_times(&[0.0]), 100.0), None);
+    }
+
+    #[test]
+    fn kiai_nps_counts_kiai_sections_only() {
+        // Kiai de 0 à 1000ms : 2 notes en 1s, les notes suivantes sont ignorées
+        let map = map_from_sections(
+            "[TimingPoints]\n0,500,4,2,0,100,1,1\n1000,-100,4,2,0,100,0,0\n\n[HitObjects]\n\
+             256,192,0,1,0,0:0:0:0:\n256,192,500,1,0,0:0:0:0:\n256,192,1500,1,0,0:0:0:0:\n256,192,2000,1,0,0:0:0:0:\n",
+        );
+        assert_eq!(calc_nps_kiai(&map), Some(2.0));
+        assert_eq!(calc_nps_kiai(&map_with_times(&[0.0, 1000.0])), None);
+    }
+
+    #[test]
+    fn type_distribution_splits_object_kinds() {
+        // Cercle, slider puis spinner sur deux parties de 500ms
+        let map = map_from_sections(
+            "[HitObjects]\n256,192,0,1,0,0:0:0:0:\n0,0,500,2,0,L|200:0,1,200\n256,192,1000,8,0,1500\n",
+        );
+        assert_eq!(calc_type_distribution(&map, 2), Some(vec![[2.0, 0.0, 0.0], [0.0, 2.0, 2.0]]));
+        assert_eq!(calc_type_distribution(&map, 0), None);
+    }
+
+    #[test]
+    fn nps_options_match_dedicated_functions() {
+        let map = load_test_map();
+        assert_eq!(calc_nps_with_options(&map, &NpsOptions::new()), calc_nps(&map));
+        assert_eq!(
+            calc_nps_with_options(&map, &NpsOptions::new().rate_mod(RateMod::DoubleTime)),
+            calc_nps_with_rate(&map, RateMod::DoubleTime)
+        );
+        assert_eq!(calc_nps_with_options(&map, &NpsOptions::new().exclude_breaks(true)), calc_nps_drain(&map));
+        assert_eq!(
+            calc_nps_with_options(&map, &NpsOptions::new().slider_weight(0.5).spinner_weight(0.0)),
+            calc_nps_weighted(&map, 0.5, 0.0)
+        );
+        assert_eq!(calc_nps_with_options(&map, &NpsOptions::new().rate(0.0)), None);
+    }
+
+    #[test]
+    fn per_second_nps_uses_absolute_seconds() {
+        // Les notes avant 0ms tombent dans la première seconde
+        let map = map_with_times(&[-100.0, 0.0, 999.0, 1000.0, 2500.0]);
+        assert_eq!(calc_nps_per_second(&map), Some(vec![3.0, 1.0, 1.0]));
+        assert_eq!(calc_nps_per_second(&map_with_times(&[])), None);
+    }
+
+    #[test]
+    fn index_range_is_inclusive() {
+        let map = map_with_times(&[0.0, 500.0, 1000.0]);
+        assert_eq!(calc_nps_range_by_index(&map, 0, 2), Some(3.0));
+        assert_eq!(calc_nps_range_by_index(&map, 1, 1), Some(0.0));
+        assert_eq!(calc_nps_range_by_index(&map, 2, 1), None);
+        assert_eq!(calc_nps_range_by_index(&map, 0, 3), None);
+    }
+
+    #[test]
+    fn local_maxima_respect_prominence_and_plateaus() {
+        let values = [0.0, 5.0, 1.0, 3.0, 3.0, 0.0, 2.0];
+        // Le plateau 3, 3 est reporté à son premier index, le bord droit est ignoré
+        assert_eq!(find_local_maxima(&values, 1.0), vec![1, 3]);
+        assert_eq!(find_local_maxima(&values, 2.5), vec![1]);
+        assert!(find_local_maxima(&[1.0, 2.0], 0.0).is_empty());
+    }
+
+    #[test]
+    fn total_nps_uses_audio_length() {
+        let map = map_with_times(&[500.0, 1000.0]);
+        assert_eq!(calc_nps_total(&map, 2000.0), Some(1.0));
+        // L'audio ne peut pas finir avant la dernière note
+        assert_eq!(calc_nps_total(&map, 900.0), None);
+        assert_eq!(calc_nps_total(&map, 0.0), None);
+    }
+
+    #[test]
+    fn dominant_snap_classifies_gaps() {
+        // 120 BPM : quatre écarts de 125ms (1/4) et un de 500ms (1/1)
+        let map = map_from_sections(
+            "[TimingPoints]\n0,500,4,2,0,100,1,0\n\n[HitObjects]\n\
+             256,192,0,1,0,0:0:0:0:\n256,192,125,1,0,0:0:0:0:\n256,192,250,1,0,0:0:0:0:\n\
+             256,192,375,1,0,0:0:0:0:\n256,192,500,1,0,0:0:0:0:\n256,192,1000,1,0,0:0:0:0:\n",
+        );
+        assert_eq!(dominant_snap(&map), Some(4));
+        assert_eq!(dominant_snap(&map_with_times(&[0.0, 125.0])), None);
+    }
+
+    #[test]
+    fn compare_distributions_subtracts_element_wise() {
+        assert_eq!(compare_distributions(&[3.0, 2.0], &[1.0, 2.0]), Some(vec![2.0, 0.0]));
+        assert_eq!(compare_distributions(&[1.0], &[1.0, 2.0]), None);
+    }
+
+    #[test]
+    fn nps_intervals_is_lazy_series() {
+        let map = load_test_map();
+        let series = calc_nps_series(&map, 1000.0).unwrap();
+        assert_eq!(nps_intervals(&map, 1000.0).collect::<Vec<_>>(), series);
+        assert_eq!(nps_intervals(&map, 1000.0).take(2).collect::<Vec<_>>(), series[..2]);
+    }
+
+    #[test]
+    fn nps_percentile_interpolates_ranks() {
+        // Densités des fenêtres ancrées triées : 2, 2, 4
+        let map = map_with_times(&[0.0, 100.0, 1000.0]);
+        assert_eq!(calc_nps_percentile(&map, 500.0, 0.0), Some(2.0));
+        assert_eq!(calc_nps_percentile(&map, 500.0, 50.0), Some(2.0));
+        assert_eq!(calc_nps_percentile(&map, 500.0, 75.0), Some(3.0));
+        assert_eq!(calc_nps_percentile(&map, 500.0, 100.0), Some(4.0));
+        assert_eq!(calc_nps_percentile(&map, 500.0, 101.0), None);
+        assert_eq!(calc_nps_percentile(&map, 0.0, 50.0), None);
+        assert_eq!(calc_nps_percentile(&map, f64::NAN, 50.0), None);
+        assert_eq!(calc_nps_percentile(&map, 500.0, f64::NAN), None);
+    }
+
+    #[test]
+    fn taiko_density_splits_don_and_kat() {
+        // Don, kat (whistle) et kat (clap) sur une seconde
+        let map = map_from_sections(
+            "[General]\nMode: 1\n\n[HitObjects]\n\
+             256,192,0,1,0,0:0:0:0:\n256,192,500,1,2,0:0:0:0:\n256,192,1000,1,8,0:0:0:0:\n",
+        );
+        assert_eq!(calc_taiko_density(&map), Some((1.0, 2.0)));
+        assert_eq!(calc_taiko_density(&map_with_times(&[0.0, 1000.0])), None);
+    }
+
+    #[test]
+    fn top_dense_windows_do_not_overlap() {
+        let map = map_with_times(&[0.0, 100.0, 200.0, 1000.0, 1100.0, 3000.0]);
+        // Les fenêtres à 100 et 1100ms chevauchent une fenêtre déjà retenue
+        assert_eq!(top_n_dense_windows(&map, 500.0, 3), vec![(0.0, 6.0), (1000.0, 4.0), (3000.0, 2.0)]);
+        assert_eq!(top_n_dense_windows(&map, 500.0, 1), vec![(0.0, 6.0)]);
+        assert!(top_n_dense_windows(&map, 500.0, 0).is_empty());
+        assert!(top_n_dense_windows(&map, f64::NAN, 3).is_empty());
+    }
+
+    #[test]
+    fn normalize_scales_to_max() {
+        assert_eq!(normalize_distribution(&[-1.0, 2.0, 4.0]), vec![0.0, 0.5, 1.0]);
+        assert_eq!(normalize_distribution(&[0.0, 0.0]), vec![0.0, 0.0]);
+        assert!(normalize_distribution(&[]).is_empty());
+    }
+
+    #[test]
+    fn cumulative_notes_end_at_object_count() {
+        let map = map_with_times(&[0.0, 250.0, 500.0, 1000.0]);
+        assert_eq!(cumulative_notes(&map, 400.0), Some(vec![(0.0, 1), (400.0, 2), (800.0, 3), (1000.0, 4)]));
+
+        let map = load_test_map();
+        let points = cumulative_notes(&map, 1000.0).unwrap();
+        assert_eq!(points.last().unwrap().1, object_count(&map));
+        assert!(points.windows(2).all(|pair| pair[0].1 <= pair[1].1));
+        assert_eq!(cumulative_notes(&map, 0.0), None);
+    }
+
+    #[test]
+    fn average_distributions_requires_equal_lengths() {
+        assert_eq!(average_distributions(&[vec![1.0, 2.0], vec![3.0, 4.0]]), Some(vec![2.0, 3.0]));
+        assert_eq!(average_distributions(&[vec![1.0], vec![3.0, 4.0]]), None);
+        assert_eq!(average_distributions(&[]), None);
+    }
+
+    #[test]
+    fn heatmap_clamps_to_playfield() {
+        // La position hors playfield (y < 0) est ramenée sur la première ligne
+        let map = map_from_sections(
+            "[HitObjects]\n100,100,0,1,0,0:0:0:0:\n400,300,100,1,0,0:0:0:0:\n600,-10,200,1,0,0:0:0:0:\n",
+        );
+        assert_eq!(playfield_heatmap(&map, 2, 2), Some(vec![vec![1, 1], vec![0, 1]]));
+        assert_eq!(playfield_heatmap(&map, 0, 2), None);
+    }
+
+    #[test]
+    fn calc_error_implements_error() {
+        let err: Box<dyn Error> = Box::new(CalcError::EmptyMap);
+        assert_eq!(err.to_string(), "beatmap has no hit objects");
+        assert_eq!(CalcError::ParseFailed.to_string(), "failed to parse beatmap");
+    }
+
+    #[test]
+    fn filtered_nps_keeps_full_drain_time() {
+        let map = map_from_sections(
+            "[HitObjects]\n256,192,0,1,0,0:0:0:0:\n0,0,500,2,0,L|200:0,1,200\n256,192,1000,8,0,1500\n",
+        );
+        assert_eq!(calc_nps_filtered(&map, |_| true), calc_nps(&map));
+        // Seul le cercle compte, sur toute la seconde de la map
+        assert_eq!(calc_nps_filtered(&map, |h| matches!(h.kind, HitObjectKind::Circle(_))), Some(1.0));
+        assert_eq!(calc_nps_filtered(&map, |_| false), Some(0.0));
+    }
+
+    #[test]
+    fn rolling_nps_slides_by_step() {
+        let map = map_with_times(&[0.0, 100.0, 600.0, 1000.0]);
+        let values: Vec<(i32, f64)> = calc_rolling_nps(&map, 500.0, 250.0)
+            .unwrap()
+            .into_iter()
+            .map(|kv| (kv.key, kv.value))
+            .collect();
+        assert_eq!(values, vec![(0, 4.0), (250, 2.0), (500, 2.0), (750, 2.0), (1000, 2.0)]);
+        assert_eq!(calc_rolling_nps(&map, 500.0, 0.0), None);
+        assert_eq!(calc_rolling_nps(&map, f64::NAN, 250.0), None);
+        assert_eq!(calc_rolling_nps(&map, 500.0, 0.5), None);
+        assert_eq!(calc_rolling_nps(&map, 500.0, f64::INFINITY), None);
+    }
+
+    #[test]
+    fn travel_distance_goes_to_arrival_part() {
+        // 500px parcourus vers la note à 500ms, aucun ensuite
+        let map = map_from_sections(
+            "[HitObjects]\n0,0,0,1,0,0:0:0:0:\n300,400,500,1,0,0:0:0:0:\n300,400,1000,1,0,0:0:0:0:\n",
+        );
+        assert_eq!(calc_travel_distance(&map, 2), Some(vec![0.0, 1000.0]));
+        // Pas de curseur en mania
+        assert_eq!(calc_travel_distance(&load_test_map(), 2), None);
+    }
+
+    #[test]
+    fn primary_bpm_picks_longest_section() {
+        // 120 BPM pendant 2s puis 240 BPM pendant 1s
+        let map = map_from_sections(
+            "[TimingPoints]\n0,500,4,2,0,100,1,0\n2000,250,4,2,0,100,1,0\n\n[HitObjects]\n\
+             256,192,0,1,0,0:0:0:0:\n256,192,3000,1,0,0:0:0:0:\n",
+        );
+        assert_eq!(primary_bpm(&map), Some(120.0));
+        assert_eq!(bpm_range(&map), Some((120.0, 240.0)));
+
+        let map = map_with_times(&[0.0]);
+        assert_eq!((primary_bpm(&map), bpm_range(&map)), (None, None));
+    }
+
+    #[test]
+    fn bursts_are_bounded_by_note_count() {
+        // Un run de 3 notes puis un de 5 à 10 NPS
+        let map = map_with_times(&[0.0, 100.0, 200.0, 1000.0, 1100.0, 1200.0, 1300.0, 1400.0, 3000.0]);
+        assert_eq!(find_bursts(&map, 2, 4, 10.0), vec![(0.0, 200.0)]);
+        assert_eq!(find_bursts(&map, 2, 5, 10.0), vec![(0.0, 200.0), (1000.0, 1400.0)]);
+        assert_eq!(find_bursts(&map, 4, 5, 10.0), vec![(1000.0, 1400.0)]);
+    }
+
+    #[test]
+    fn summary_combines_density_metrics() {
+        // Stream de 11 notes sur 1s puis une note isolée
+        let mut times: Vec<f64> = (0..=10).map(|i| i as f64 * 100.0).collect();
+        times.push(3000.0);
+        let summary = summarize(&map_with_times(&times), 1000.0, 10.0).unwrap();
+
+        assert_eq!(
+            summary,
+            MapDensitySummary {
+                overall_nps: 4.0,
+                peak_nps: 10.0,
+                peak_time_ms: 0.0,
+                stream_count: 1,
+                total_objects: 12,
+                drain_time_ms: 3000.0,
+            }
+        );
+        assert_eq!(summarize(&map_with_times(&[]), 1000.0, 10.0), None);
+    }
+
+    #[test]
+    fn consistency_ignores_empty_parts() {
+        // Parties de 500ms : 3 notes puis 1, soit 6 et 2 NPS
+        assert_eq!(density_consistency(&map_with_times(&[0.0, 100.0, 200.0, 1000.0]), 2), Some(0.5));
+        // Les parties vides d'une pause ne rendent pas la map irrégulière
+        assert_eq!(density_consistency(&map_with_times(&[0.0, 100.0, 2000.0, 2100.0]), 4), Some(0.0));
+        assert_eq!(density_consistency(&map_with_times(&[0.0, 100.0]), 0), None);
+    }
+
+    #[test]
+    fn drain_time_and_object_count() {
+        let map = map_with_times(&[100.0, 350.0, 600.0]);
+        assert_eq!(drain_time_ms(&map), Some(500.0));
+        assert_eq!(object_count(&map), 3);
+
+        let map = map_with_times(&[]);
+        assert_eq!((drain_time_ms(&map), object_count(&map)), (None, 0));
+    }
+
+    #[test]
+    fn sustained_spreads_holds_and_keeps_object_mass() {
+        // Un cercle et un hold de 0 à 1000ms, le hold pèse moitié sur chaque intervalle
+        let map = map_from_sections(
+            "[General]\nMode: 3\n\n[Difficulty]\nCircleSize:4\n\n[HitObjects]\n\
+             64,192,0,1,0,0:0:0:0:\n192,192,0,128,0,1000:0:0:0:0:\n",
+        );
+        assert_eq!(calc_nps_sustained(&map, 500.0), Some(vec![3.0, 1.0, 0.0]));
+
+        // Chaque objet est réparti sans perte : la somme redonne le nombre d'objets
+        let map = load_test_map();
+        let total: f64 = calc_nps_sustained(&map, 1000.0).unwrap().iter().sum();
+        assert!((total - object_count(&map) as f64).abs() < 1e-6);
+        assert_eq!(calc_nps_sustained(&map, 0.0), None);
+    }
+
+    #[test]
+    fn resample_keeps_endpoints() {
+        assert_eq!(resample_distribution(&[0.0, 10.0], 5), vec![0.0, 2.5, 5.0, 7.5, 10.0]);
+        assert_eq!(resample_distribution(&[0.0, 5.0, 10.0, 15.0, 20.0], 3), vec![0.0, 10.0, 20.0]);
+        assert_eq!(resample_distribution(&[4.0], 3), vec![4.0; 3]);
+        assert_eq!(resample_distribution(&[], 2), vec![0.0; 2]);
+    }
+
+    #[test]
+    fn svg_bars_scale_to_max() {
+        use crate::render::render_distribution_svg;
+
+        let svg = render_distribution_svg(&[1.0, 2.0], 100, 50);
+        assert!(svg.starts_with(r#"<svg xmlns="http://www.w3.org/2000/svg" width="100" height="50""#));
+        assert!(svg.contains(r#"<rect x="0.00" y="25.00" width="50.00" height="25.00"/>"#));
+        assert!(svg.contains(r#"<rect x="50.00" y="0.00" width="50.00" height="50.00"/>"#));
+        assert!(!render_distribution_svg(&[0.0, 0.0], 100, 50).contains("<rect"));
+    }
+
+    #[test]
+    fn note_gaps_between_consecutive_objects() {
+        assert_eq!(note_gaps(&map_with_times(&[0.0, 100.0, 100.0, 350.0])), vec![100.0, 0.0, 250.0]);
+        assert!(note_gaps(&map_with_times(&[0.0])).is_empty());
+    }
+
+    #[test]
+    fn trimmed_mean_without_trim_is_mean() {
+        let map = load_test_map();
+        let mean = distribution_stats(&calc_distribution(&map, 100).unwrap()).unwrap().mean;
+        assert!((trimmed_mean_nps(&map, 100, 0.0).unwrap() - mean).abs() < 1e-9);
+
+        // 4 parties à 6, 2, 0 et 4 NPS : on retire le min et le max
+        let map = map_with_times(&[0.0, 100.0, 200.0, 600.0, 1500.0, 2000.0]);
+        assert_eq!(calc_distribution(&map, 4), Some(vec![6.0, 2.0, 0.0, 4.0]));
+        assert_eq!(trimmed_mean_nps(&map, 4, 0.25), Some(3.0));
+        assert_eq!(trimmed_mean_nps(&map, 4, 0.5), None);
+    }
+
+    #[test]
+    fn merge_spans_joins_close_spans() {
+        let spans = [(0.0, 100.0), (150.0, 300.0), (250.0, 400.0), (1000.0, 1100.0)];
+        assert_eq!(merge_spans(&spans, 100.0), vec![(0.0, 400.0), (1000.0, 1100.0)]);
+        // Écart pile égal à max_gap_ms : pas de fusion
+        assert_eq!(merge_spans(&[(0.0, 100.0), (150.0, 200.0)], 50.0), vec![(0.0, 100.0), (150.0, 200.0)]);
+        assert!(merge_spans(&[], 100.0).is_empty());
+    }
+
+    #[test]
+    fn density_difficulty_rewards_streams() {
+        // Sans stream : 0.4 * 2 + 0.6 * 1
+        assert!((density_difficulty(&map_with_times(&[0.0, 1000.0])).unwrap() - 1.4).abs() < 1e-9);
+
+        // 11 notes à 10 NPS sur 1s, toutes dans un stream : (0.4 * 11 + 0.6 * 10) * 1.5
+        let times: Vec<f64> = (0..=10).map(|i| i as f64 * 100.0).collect();
+        assert!((density_difficulty(&map_with_times(&times)).unwrap() - 15.6).abs() < 1e-9);
+        assert_eq!(density_difficulty(&map_with_times(&[])), None);
+    }
+
+    #[test]
+    fn boundaries_define_half_open_intervals() {
+        let map = map_with_times(&[0.0, 500.0, 1000.0]);
+        // La note à 1000ms est hors du dernier intervalle [500, 1000)
+        assert_eq!(calc_nps_at_boundaries(&map, &[0.0, 500.0, 1000.0]), Some(vec![2.0, 2.0]));
+        assert_eq!(calc_nps_at_boundaries(&map, &[0.0, 1000.0, 500.0]), None);
+        assert_eq!(calc_nps_at_boundaries(&map, &[0.0]), None);
+    }
+
+    #[test]
+    fn timing_sections_split_at_uninherited_points() {
+        let map = map_from_sections(
+            "[TimingPoints]\n0,500,4,2,0,100,1,0\n1000,250,4,2,0,100,1,0\n\n[HitObjects]\n\
+             256,192,0,1,0,0:0:0:0:\n256,192,500,1,0,0:0:0:0:\n256,192,1000,1,0,0:0:0:0:\n\
+             256,192,1250,1,0,0:0:0:0:\n256,192,1500,1,0,0:0:0:0:\n256,192,2000,1,0,0:0:0:0:\n",
+        );
+        // La dernière section garde la dernière note
+        assert_eq!(calc_nps_per_timing_section(&map), Some(vec![(0.0, 1000.0, 2.0), (1000.0, 2000.0, 4.0)]));
+
+        let map = map_with_times(&[0.0, 1000.0]);
+        assert_eq!(calc_nps_per_timing_section(&map), Some(vec![(0.0, 1000.0, 2.0)]));
+    }
+
+    #[test]
+    fn measures_follow_meter() {
+        // 120 BPM en 4/4 : mesures de 2000ms
+        let map = map_from_sections(
+            "[TimingPoints]\n0,500,4,2,0,100,1,0\n\n[HitObjects]\n\
+             256,192,0,1,0,0:0:0:0:\n256,192,500,1,0,0:0:0:0:\n256,192,1000,1,0,0:0:0:0:\n\
+             256,192,2000,1,0,0:0:0:0:\n256,192,3000,1,0,0:0:0:0:\n256,192,4000,1,0,0:0:0:0:\n",
+        );
+        assert_eq!(calc_nps_per_measure(&map), Some(vec![1.5, 1.0, 0.5]));
+        assert_eq!(calc_nps_per_measure(&map_with_times(&[0.0, 1000.0])), None);
+    }
+
+    #[test]
+    fn from_and_until_wrap_time_ranges() {
+        let map = map_with_times(&[0.0, 500.0, 1000.0, 2000.0]);
+        // calc_nps_from inclut la dernière note
+        assert_eq!(calc_nps_from(&map, 500.0), Some(2.0));
+        assert_eq!(calc_nps_from(&map, 2000.0), Some(0.0));
+        // calc_nps_until exclut end_time
+        assert_eq!(calc_nps_until(&map, 1000.0), Some(2.0));
+        assert_eq!(calc_nps_until(&map, 1000.0), calc_nps_range_by_time(&map, 0.0, 1000.0));
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn batch_keeps_input_order() {
+        let paths = [
+            PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/8thera.osu")),
+            PathBuf::from("absent.osu"),
+        ];
+        assert_eq!(calc_nps_batch(&paths), vec![calc_nps(&load_test_map()), None]);
+    }
+
+    #[test]
+    fn nth_note_time_is_bounds_checked() {
+        let map = map_with_times(&[0.0, 500.0]);
+        assert_eq!(nth_note_time(&map, 1), Some(500.0));
+        assert_eq!(nth_note_time(&map, 2), None);
+    }
+
+    #[test]
+    fn split_series_returns_columns() {
+        let series = calc_nps_series(&map_with_times(&[0.0, 250.0, 1000.0]), 500.0).unwrap();
+        assert_eq!(split_series(&series), (vec![0, 500, 1000], vec![4.0, 0.0, 2.0]));
+        assert_eq!(split_series(&[]), (vec![], vec![]));
+    }
+
+    #[test]
+    fn hardest_window_stays_inside_the_map() {
+        // La fenêtre de queue [800, 1300] garde 4 notes, dernière incluse
+        let map = map_with_times(&[0.0, 100.0, 200.0, 1000.0, 1100.0, 1200.0, 1300.0]);
+        assert_eq!(hardest_window(&map, 500.0), Some((800.0, 8.0)));
+
+        // Égalité entre la première fenêtre et la queue : la plus tôt l'emporte
+        let map = map_with_times(&[0.0, 100.0, 1000.0, 1100.0]);
+        assert_eq!(hardest_window(&map, 500.0), Some((0.0, 4.0)));
+
+        // Map plus courte que la fenêtre : toutes les notes depuis la première
+        assert_eq!(hardest_window(&map_with_times(&[200.0, 300.0]), 500.0), Some((200.0, 4.0)));
+        assert_eq!(hardest_window(&map, 0.0), None);
+        assert_eq!(hardest_window(&map, f64::NAN), None);
+        assert_eq!(hardest_window(&map, f64::INFINITY), None);
+    }
+
+    #[test]
+    fn instantaneous_nps_uses_neighbor_slices() {
+        let map = map_with_times(&[0.0, 100.0, 200.0, 1000.0]);
+        let expected = [20.0, 15.0, 3.0 / 0.9, 2.5];
+        assert!(distributions_approx_eq(&instantaneous_nps(&map, 1), &expected, 1e-9));
+        // Sans voisin la tranche est une seule note, comptée comme calc_nps
+        assert_eq!(instantaneous_nps(&map, 0), vec![1.0; 4]);
+        assert!(instantaneous_nps(&map_with_times(&[]), 1).is_empty());
+    }
+
+    #[test]
+    fn spinner_time_is_removed_from_drain() {
+        // Spinner de 1000 à 2000ms entre deux cercles : 2 notes sur 2s
+        let map = map_from_sections(
+            "[HitObjects]\n256,192,0,1,0,0:0:0:0:\n256,192,1000,8,0,2000\n256,192,3000,1,0,0:0:0:0:\n",
+        );
+        assert_eq!(calc_nps_excluding_spinners(&map), Some(1.0));
+        let map = map_with_times(&[0.0, 1000.0]);
+        assert_eq!(calc_nps_excluding_spinners(&map), calc_nps(&map));
+    }
+
+    #[test]
+    fn nps_at_time_centers_the_window() {
+        let map = map_with_times(&[0.0, 400.0, 600.0, 1000.0]);
+        // [300, 700) autour de 500ms
+        assert_eq!(nps_at_time(&map, 500.0, 400.0), Some(5.0));
+        assert_eq!(nps_at_time(&map, 500.0, 0.0), None);
+        assert_eq!(nps_at_time(&map, 500.0, f64::NAN), None);
+        assert_eq!(nps_at_time(&map, 500.0, f64::INFINITY), None);
+        assert_eq!(nps_at_time(&map_with_times(&[]), 500.0, 400.0), None);
+    }
+
+    #[test]
+    fn decimate_keeps_bucket_maxima() {
+        assert_eq!(decimate_peaks(&[1.0, 5.0, 2.0, 2.0, 9.0, 0.0], 3), vec![5.0, 2.0, 9.0]);
+        assert_eq!(decimate_peaks(&[1.0, 2.0], 3), vec![1.0, 2.0]);
+    }
+
+    #[test]
+    fn gradient_is_per_second_difference() {
+        assert_eq!(density_gradient(&[1.0, 3.0, 2.0], 500.0), vec![0.0, 4.0, -2.0]);
+        assert_eq!(density_gradient(&[1.0, 3.0], 0.0), vec![0.0, 0.0]);
+        assert!(density_gradient(&[], 500.0).is_empty());
+    }
+
+    #[test]
+    fn catch_density_counts_fruits_and_bananas() {
+        // Juice stream de 200px : tête, queue et 1 droplet, puis un banana shower de 1s
+        // dont l'espacement est divisé par deux jusqu'à 62.5ms, soit 17 bananes
+        let map = map_from_sections(
+            "[General]\nMode: 2\n\n[Difficulty]\nSliderMultiplier:1\nSliderTickRate:1\n\n\
+             [TimingPoints]\n0,500,4,2,0,100,1,0\n\n[HitObjects]\n\
+             0,0,0,2,0,L|200:0,1,200\n256,192,1000,8,0,2000\n",
+        );
+        assert_eq!(calc_catch_density(&map), Some(20.0));
+        assert_eq!(calc_catch_density(&map_with_times(&[0.0, 1000.0])), None);
+    }
+
+    #[test]
+    fn sanitize_drops_clamps_and_sorts() {
+        let mut map = map_with_times(&[0.0, 100.0, 200.0, 300.0]);
+        map.hit_objects[1].start_time = f64::NAN;
+        map.hit_objects[2].start_time = -50.0;
+        map.hit_objects[3].start_time = f64::INFINITY;
+
+        assert_eq!(sanitize_timings(&mut map), 2);
+        let times: Vec<f64> = map.hit_objects.iter().map(|h| h.start_time).collect();
+        assert_eq!(times, vec![0.0, 0.0]);
+
+        // Une map déjà saine n'est pas modifiée
+        let mut map = load_test_map();
+        assert_eq!(sanitize_timings(&mut map), 0);
+        assert_eq!(map.hit_objects, load_test_map().hit_objects);
+    }
+
+    #[test]
+    fn percent_range_includes_last_note_at_100() {
+        let map = map_with_times(&[0.0, 500.0, 1000.0, 2000.0]);
+        assert_eq!(calc_nps_percent_range(&map, 0.0, 50.0), Some(2.0));
+        assert_eq!(calc_nps_percent_range(&map, 50.0, 100.0), Some(2.0));
+        assert_eq!(calc_nps_percent_range(&map, 0.0, 100.0), calc_nps(&map));
+        assert_eq!(calc_nps_percent_range(&map, 50.0, 50.0), None);
+        assert_eq!(calc_nps_percent_range(&map, 0.0, 101.0), None);
+
+        // Accord sur un seul instant : même valeur que calc_nps
+        let chord = map_with_times(&[1000.0, 1000.0, 1000.0]);
+        assert_eq!(calc_nps_percent_range(&chord, 0.0, 100.0), Some(3.0));
+        assert_eq!(calc_nps_percent_range(&chord, 0.0, 100.0), calc_nps(&chord));
+        assert_eq!(calc_nps_percent_range(&map_with_times(&[500.0]), 25.0, 75.0), Some(1.0));
+    }
+
+    #[test]
+    fn analysis_combines_and_serializes() {
+        let map = load_test_map();
+        let analysis = analyze(&map, 10, 1000.0).unwrap();
+        let distribution = calc_distribution(&map, 10).unwrap();
+
+        assert_eq!(analysis.overall_nps, calc_nps(&map).unwrap());
+        assert_eq!(analysis.peak_nps, calc_peak_nps(&map, 1000.0).unwrap());
+        assert_eq!(analysis.stats, distribution_stats(&distribution).unwrap());
+        assert_eq!(analysis.distribution, distribution);
+
+        let json: serde_json::Value = serde_json::to_value(&analysis).unwrap();
+        assert_eq!(json["distribution"].as_array().unwrap().len(), 10);
+        assert!(json["stats"]["median"].is_number());
+        assert_eq!(analyze(&map, 0, 1000.0), None);
+    }
+
+    #[test]
+    fn longest_gap_prefers_first_on_ties() {
+        let map = map_with_times(&[0.0, 500.0, 600.0, 1100.0, 1200.0]);
+        assert_eq!(longest_gap(&map), Some((0.0, 500.0)));
+        assert_eq!(longest_gap(&map_with_times(&[0.0, 100.0, 1000.0])), Some((100.0, 1000.0)));
+        assert_eq!(longest_gap(&map_with_times(&[0.0])), None);
+    }
+
+    #[test]
+    fn nps_series_rejects_intervals_that_break_keys() {
+        // Sous 1ms les clés entières se répéteraient
+        let map = map_with_times(&[0.0, 0.4, 1.0, 2.0]);
+        assert_eq!(calc_nps_series(&map, 0.5), None);
+        assert_eq!(nps_intervals(&map, 0.5).count(), 0);
+        assert_eq!(calc_nps_series(&map, f64::INFINITY), None);
+        assert_eq!(calc_nps_series(&map, f64::NAN), None);
+        assert_eq!(calc_nps_series(&map, 1.0).unwrap().len(), 3);
+
+        // Un pas infini garde une seule fenêtre partant de la première note
+        let map = map_with_times(&[100.0, 200.0]);
+        assert_eq!(time_windows(&map, f64::INFINITY), vec![(100.0, 2)]);
+    }
+}
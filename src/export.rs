@@ -0,0 +1,30 @@
+use std::fmt::Write;
+
+use crate::calc::{KeyValue, window_start};
+
+// Les parties commencent à first_time_ms (la première note), comme les clés de calc_nps_series
+pub fn distribution_to_csv(values: &[f64], first_time_ms: f64, part_duration_ms: f64) -> String {
+    let mut csv = String::from("start_time_ms,nps\n");
+
+    for (i, value) in values.iter().enumerate() {
+        let start_time_ms = window_start(first_time_ms, part_duration_ms, i);
+        // Écrire dans une String ne peut pas échouer
+        let _ = writeln!(csv, "{},{}", start_time_ms, value);
+    }
+
+    csv
+}
+
+pub fn distribution_to_json(values: &[f64], first_time_ms: f64, part_duration_ms: f64) -> Result<String, String> {
+    let series: Vec<KeyValue> = values
+        .iter()
+        .enumerate()
+        .map(|(i, &value)| KeyValue {
+            // Tronqué en ms entières comme les KeyValue de calc_nps_series
+            key: window_start(first_time_ms, part_duration_ms, i) as i32,
+            value,
+        })
+        .collect();
+
+    serde_json::to_string(&series).map_err(|e| e.to_string())
+}
@@ -1,3 +1,52 @@
 pub mod calc;
+pub mod export;
+pub mod render;
 
-pub use calc::{calc_nps, calc_nps_range_by_hitobjects, calc_nps_range_by_time, calc_distribution, calc_distribution_2, calc_distribution_smart}; 
\ No newline at end of file
+pub use calc::{
+    calc_nps, calc_nps_range_by_hitobjects, calc_nps_range_by_time, calc_nps_range_by_index,
+    calc_nps_at_boundaries, calc_nps_from, calc_nps_until, calc_nps_percent_range, nps_at_time,
+};
+pub use calc::{
+    calc_nps_with_rate, calc_nps_weighted, calc_nps_drain, calc_nps_with_options, calc_nps_kiai,
+    calc_nps_total, calc_nps_filtered, calc_nps_with_ticks, calc_nps_excluding_spinners,
+    calc_notes_per_beat, calc_nps_per_column, calc_taiko_density, calc_catch_density,
+    calc_nps_per_timing_section, calc_nps_per_measure,
+};
+pub use calc::{
+    calc_distribution, calc_distribution_2, calc_distribution_smart, calc_count_distribution,
+    calc_type_distribution, calc_travel_distance, calc_nps_per_second, calc_nps_sustained,
+};
+pub use calc::{
+    calc_peak_nps, calc_peak_nps_at, aggregate_peak_nps, calc_nps_percentile,
+    top_n_dense_windows, calc_min_nps_window, hardest_window, instantaneous_nps,
+};
+pub use calc::{
+    calc_nps_series, calc_nps_series_from_times, calc_rolling_nps, nps_intervals, time_windows,
+    cumulative_notes,
+};
+pub use calc::{
+    smooth_distribution, distribution_stats, compare_distributions, normalize_distribution,
+    average_distributions, resample_distribution, decimate_peaks, density_gradient,
+    find_local_maxima, split_series,
+};
+pub use calc::{
+    find_streams, find_bursts, merge_spans, interval_histogram, dominant_snap, note_gaps,
+    longest_gap, primary_bpm, bpm_range,
+};
+pub use calc::{
+    summarize, analyze, density_consistency, density_difficulty, trimmed_mean_nps,
+    playfield_heatmap, drain_time_ms, object_count, nth_note_time,
+};
+pub use calc::{
+    calc_nps_from_bytes, parse_hit_objects_reader, parse_hit_objects_lenient, sanitize_timings,
+};
+pub use calc::{
+    CalcError, DistributionStats, KeyValue, MapAnalysis, MapDensitySummary, NpsOptions, ParseError,
+    RateMod,
+};
+pub use export::{distribution_to_csv, distribution_to_json};
+pub use render::render_distribution_svg;
+#[cfg(feature = "rayon")]
+pub use calc::{calc_distribution_parallel, calc_nps_batch};
+#[cfg(feature = "zip")]
+pub use calc::calc_nps_from_osz;
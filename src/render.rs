@@ -0,0 +1,29 @@
+use std::fmt::Write;
+
+pub fn render_distribution_svg(values: &[f64], width: u32, height: u32) -> String {
+    let mut svg = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}">"#
+    );
+
+    // L'axe y est mis à l'échelle sur la valeur max, une barre par partie
+    let max = values.iter().copied().fold(0.0, f64::max);
+    if !values.is_empty() && max > 0.0 {
+        let bar_width = width as f64 / values.len() as f64;
+
+        for (i, &value) in values.iter().enumerate() {
+            let bar_height = value.max(0.0) / max * height as f64;
+            // Écrire dans une String ne peut pas échouer
+            let _ = write!(
+                svg,
+                r#"<rect x="{:.2}" y="{:.2}" width="{:.2}" height="{:.2}"/>"#,
+                i as f64 * bar_width,
+                height as f64 - bar_height,
+                bar_width,
+                bar_height,
+            );
+        }
+    }
+
+    svg.push_str("</svg>");
+    svg
+}